@@ -3,12 +3,16 @@
  */
 use futures::Future;
 use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::hash::Hash;
 use std::{
     collections::HashMap,
+    io,
+    path::{Path, PathBuf},
     pin::Pin,
     sync::{Arc, Weak},
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 use tokio::sync::broadcast;
 use tracing::debug;
@@ -20,7 +24,16 @@ where
     V: Clone + Send + Sync + 'static,
 {
     timeout: Option<Duration>,
+    /// How long expired values are still served while a new value is fetched in the background
+    stale_while_revalidate: Option<Duration>,
+    /// How long expired values are still served when fetching a new value fails
+    stale_if_error: Option<Duration>,
+    /// Failures aren't cached without them
+    error_timeouts: Option<ErrorTimeouts>,
+    /// Maximum number of items, the least recently used items are evicted to stay below it
+    max_items: Option<usize>,
     items: Arc<Mutex<HashMap<K, CacheItem<V>>>>,
+    store: Option<Store>,
 }
 
 struct CacheItem<T>
@@ -29,6 +42,27 @@ where
 {
     cached: Option<(Instant, T)>,
     task: Option<Weak<broadcast::Sender<Result<T, CacheError>>>>,
+    last_used: Instant,
+    /// Number of requests since the value was fetched
+    hits: u32,
+    /// The last fetch failed, it isn't retried until it times out
+    failed: Option<Failure>,
+}
+
+struct Failure {
+    at: Instant,
+    error: CacheError,
+    /// Number of fetches in a row that failed
+    count: u32,
+}
+
+impl<T> CacheItem<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn is_fetching(&self) -> bool {
+        self.task.as_ref().and_then(Weak::upgrade).is_some()
+    }
 }
 
 impl<T> Default for CacheItem<T>
@@ -39,82 +73,794 @@ where
         Self {
             cached: None,
             task: None,
+            last_used: Instant::now(),
+            hits: 0,
+            failed: None,
         }
     }
 }
 
+/// A value from the cache
+#[derive(Debug, Clone)]
+pub struct Cached<T> {
+    pub value: T,
+    /// Time until the value expires, `None` if it never expires
+    pub expires_in: Option<Duration>,
+    /// The value has expired, because it's being refreshed or fetching a new value failed
+    pub stale: bool,
+}
+
 #[derive(Debug, Clone, thiserror::Error)]
-#[error("{0}")]
-pub struct CacheError(String);
+#[error("{message}")]
+pub struct CacheError {
+    message: String,
+    /// The value doesn't exist, so fetching it again is unlikely to succeed soon
+    not_found: bool,
+}
+
+impl CacheError {
+    pub fn new(err: impl std::fmt::Display) -> Self {
+        Self {
+            message: err.to_string(),
+            not_found: false,
+        }
+    }
+
+    pub fn not_found(err: impl std::fmt::Display) -> Self {
+        Self {
+            message: err.to_string(),
+            not_found: true,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        self.not_found
+    }
+}
+
+/// How long failed fetches are cached, so that they aren't retried on every request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorTimeouts {
+    /// After the first failure, it's doubled for each failure in a row
+    pub transient: Duration,
+    /// Maximum of the doubled timeout
+    pub max_transient: Duration,
+    /// For values that don't exist
+    pub not_found: Duration,
+}
+
+impl ErrorTimeouts {
+    fn timeout(&self, error: &CacheError, count: u32) -> Duration {
+        if error.is_not_found() {
+            return self.not_found;
+        }
+        let factor = 2u32.saturating_pow(count.saturating_sub(1));
+        self.transient
+            .saturating_mul(factor)
+            .min(self.max_transient)
+    }
+
+    fn is_recent(&self, failure: &Failure) -> bool {
+        failure.at.elapsed() < self.timeout(&failure.error, failure.count)
+    }
+
+    /// Failures are remembered for as long as the next one would be cached, so that the timeout
+    /// keeps growing while the fetches keep failing
+    fn is_remembered(&self, failure: &Failure) -> bool {
+        failure.at.elapsed() < self.timeout(&failure.error, failure.count + 1)
+    }
+}
 
 pub type BoxFut<'a, O> = Pin<Box<dyn Future<Output = O> + Send + 'a>>;
 
 impl<K, V> Cache<K, V>
 where
-    K: Eq + Hash + std::fmt::Debug + Clone + Send + Sync + 'static,
-    V: Clone + Send + Sync + 'static,
+    K: Eq + Hash + std::fmt::Debug + Clone + Send + Sync + Serialize + DeserializeOwned + 'static,
+    V: Clone + Send + Sync + Serialize + DeserializeOwned + 'static,
 {
     pub fn new(timeout: Option<Duration>) -> Self {
         Self {
             items: Default::default(),
             timeout,
+            stale_while_revalidate: None,
+            stale_if_error: None,
+            error_timeouts: None,
+            max_items: None,
+            store: None,
+        }
+    }
+
+    /// Serves expired values for up to `max_stale` after they expire, while a new value is fetched
+    /// in the background
+    pub fn with_stale_while_revalidate(mut self, max_stale: Duration) -> Self {
+        self.stale_while_revalidate = Some(max_stale);
+        self
+    }
+
+    /// Serves expired values for up to `max_stale` after they expire when fetching a new value
+    /// fails
+    pub fn with_stale_if_error(mut self, max_stale: Duration) -> Self {
+        self.stale_if_error = Some(max_stale);
+        self
+    }
+
+    /// Caches failed fetches, so that they aren't retried until they time out
+    pub fn with_error_timeouts(mut self, error_timeouts: ErrorTimeouts) -> Self {
+        self.error_timeouts = Some(error_timeouts);
+        self
+    }
+
+    /// Limits the number of items, evicting the least recently used items
+    pub fn with_max_items(mut self, max_items: usize) -> Self {
+        self.max_items = Some(max_items);
+        let evicted = self.evict(&mut self.items.lock(), 0);
+        self.remove_stored(evicted);
+        self
+    }
+
+    /// Keeps the values in a [`Store`], the values that can still be served are loaded from it,
+    /// so it's set after the timeouts
+    pub fn with_store(mut self, store: Store) -> Self {
+        let max_age = self.max_age();
+        let mut items = self.items.lock();
+        for stored in store.load::<K, V>() {
+            let age = SystemTime::now()
+                .duration_since(stored.fetched_at)
+                .unwrap_or_default();
+            if max_age.is_some_and(|max_age| age >= max_age) {
+                store.remove(&stored.key);
+                continue;
+            }
+            let fetched_at = saturating_sub(Instant::now(), age);
+            let item = CacheItem {
+                cached: Some((fetched_at, stored.value)),
+                task: None,
+                last_used: fetched_at,
+                hits: 0,
+                failed: None,
+            };
+            items.insert(stored.key, item);
+        }
+        debug!("loaded {} items from {}", items.len(), store.dir.display());
+        drop(items);
+        self.store = Some(store);
+        self
+    }
+
+    /// Removes the expired items and failed fetches that timed out, so that they don't take up
+    /// memory
+    pub fn sweep(&self) {
+        let removed: Vec<K> = {
+            let mut items = self.items.lock();
+            let removed: Vec<K> = items
+                .iter()
+                .filter(|(_, item)| {
+                    !item.is_fetching() && !self.is_servable(item) && !self.remembers_failure(item)
+                })
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in &removed {
+                items.remove(key);
+            }
+            removed
+        };
+        if !removed.is_empty() {
+            debug!("swept {} expired items", removed.len());
+        }
+        self.remove_stored(removed);
+    }
+
+    /// Sweeps the cache periodically in a background task
+    pub fn spawn_sweeper(&self, interval: Duration) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            loop {
+                interval.tick().await;
+                cache.sweep();
+            }
+        });
+    }
+
+    /// Keys of the cached values that expire within `within` and were requested at least
+    /// `min_hits` times since they were fetched, to refresh them before they expire
+    pub fn expiring(&self, within: Duration, min_hits: u32) -> Vec<K> {
+        let Some(timeout) = self.timeout else {
+            return Vec::new();
+        };
+        let items = self.items.lock();
+        items
+            .iter()
+            .filter(|(_, item)| item.hits >= min_hits && !item.is_fetching())
+            // failed fetches are retried by requests once they time out
+            .filter(|(_, item)| self.recent_failure(item).is_none())
+            .filter(|(_, item)| {
+                item.cached.as_ref().is_some_and(|(fetched_at, _)| {
+                    timeout.saturating_sub(fetched_at.elapsed()) <= within
+                })
+            })
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    /// Fetches a new value for a cached key in the background, unless it's already being fetched
+    pub fn refresh<F, E>(&self, key: K, f: F)
+    where
+        F: FnOnce() -> BoxFut<'static, Result<V, E>>,
+        E: Into<CacheError> + 'static,
+    {
+        let mut items = self.items.lock();
+        if let Some(item) = items.get_mut(&key).filter(|item| !item.is_fetching()) {
+            debug!("refreshing {key:?}");
+            self.fetch(item, key, f);
+        }
+    }
+
+    /// Maximum age of a value that is still served, `None` if values never expire
+    fn max_age(&self) -> Option<Duration> {
+        let max_stale = self.stale_while_revalidate.max(self.stale_if_error);
+        Some(self.timeout? + max_stale.unwrap_or_default())
+    }
+
+    fn is_servable(&self, item: &CacheItem<V>) -> bool {
+        item.cached.as_ref().is_some_and(|(fetched_at, _)| {
+            self.max_age()
+                .is_none_or(|max_age| fetched_at.elapsed() < max_age)
+        })
+    }
+
+    /// The cached value of an item, if it's at most `max_stale` past its timeout
+    fn entry(&self, item: &CacheItem<V>, max_stale: Duration) -> Option<Cached<V>> {
+        let (fetched_at, value) = item.cached.as_ref()?;
+        let age = fetched_at.elapsed();
+        let Some(timeout) = self.timeout else {
+            return Some(Cached {
+                value: value.clone(),
+                expires_in: None,
+                stale: false,
+            });
+        };
+        (age < timeout + max_stale).then(|| Cached {
+            value: value.clone(),
+            expires_in: Some(timeout.saturating_sub(age)),
+            stale: age >= timeout,
+        })
+    }
+
+    /// The error of the last fetch if it failed and hasn't timed out
+    fn recent_failure(&self, item: &CacheItem<V>) -> Option<CacheError> {
+        let failure = item.failed.as_ref()?;
+        self.error_timeouts?
+            .is_recent(failure)
+            .then(|| failure.error.clone())
+    }
+
+    fn remembers_failure(&self, item: &CacheItem<V>) -> bool {
+        item.failed.as_ref().is_some_and(|failure| {
+            self.error_timeouts
+                .is_some_and(|timeouts| timeouts.is_remembered(failure))
+        })
+    }
+
+    /// Removes the least recently used items until there is room for `room` new items, items
+    /// that are being fetched are kept
+    fn evict(&self, items: &mut HashMap<K, CacheItem<V>>, room: usize) -> Vec<K> {
+        let Some(max_items) = self.max_items else {
+            return Vec::new();
+        };
+        let mut evicted = Vec::new();
+        while items.len() + room > max_items {
+            let lru = items
+                .iter()
+                .filter(|(_, item)| !item.is_fetching())
+                .min_by_key(|(_, item)| item.last_used)
+                .map(|(key, _)| key.clone());
+            let Some(key) = lru else {
+                break;
+            };
+            items.remove(&key);
+            evicted.push(key);
+        }
+        if !evicted.is_empty() {
+            debug!("evicted {} least recently used items", evicted.len());
+        }
+        evicted
+    }
+
+    /// Removes the files of items that are no longer cached
+    fn remove_stored(&self, keys: Vec<K>) {
+        if let Some(store) = self.store.clone().filter(|_| !keys.is_empty()) {
+            tokio::task::spawn_blocking(move || {
+                for key in keys {
+                    store.remove(&key);
+                }
+            });
         }
     }
 
     pub async fn get_cached<F, E>(&self, key: K, f: F) -> Result<V, CacheError>
     where
         F: FnOnce() -> BoxFut<'static, Result<V, E>>,
-        E: std::fmt::Display + 'static,
+        E: Into<CacheError> + 'static,
     {
+        self.get_cached_or_stale(key, f)
+            .await
+            .map(|cached| cached.value)
+    }
+
+    /// Gets a value like [`Cache::get_cached`], but may return an expired value while it's
+    /// refreshed or when fetching a new value fails
+    pub async fn get_cached_or_stale<F, E>(&self, key: K, f: F) -> Result<Cached<V>, CacheError>
+    where
+        F: FnOnce() -> BoxFut<'static, Result<V, E>>,
+        E: Into<CacheError> + 'static,
+    {
+        let mut evicted = Vec::new();
         let mut rx = {
             let mut items = self.items.lock();
 
+            if !items.contains_key(&key) {
+                evicted = self.evict(&mut items, 1);
+            }
             // Get exsisting or create new item
             let item = items.entry(key.clone()).or_default();
+            item.last_used = Instant::now();
+
+            item.hits += 1;
 
             // Check if item is in the cache
-            if let Some((fetched_at, value)) = item.cached.as_ref() {
-                if self.timeout.is_none() || Some(fetched_at.elapsed()) < self.timeout {
-                    return Ok(value.clone());
-                } else {
-                    debug!("{key:?} has timed-out, fetching new value");
+            if let Some(cached) = self.entry(item, Duration::ZERO) {
+                return Ok(cached);
+            }
+            if let Some(err) = self.recent_failure(item) {
+                debug!("{key:?} failed recently, not fetching new value");
+                return self
+                    .entry(item, self.stale_if_error.unwrap_or_default())
+                    .ok_or(err);
+            }
+            if let Some(cached) = self
+                .stale_while_revalidate
+                .and_then(|max_stale| self.entry(item, max_stale))
+            {
+                if !item.is_fetching() {
+                    debug!("{key:?} is stale, fetching new value in the background");
+                    self.fetch(item, key, f);
                 }
+                return Ok(cached);
+            }
+            if item.cached.is_some() {
+                debug!("{key:?} has timed-out, fetching new value");
             }
 
             if let Some(tasks) = item.task.as_ref().and_then(Weak::upgrade) {
                 // Subscribe to the task's channel if already being fetched
                 tasks.subscribe()
             } else {
-                // Create a new channel to fetch the value
-                let (tx, rx) = broadcast::channel::<Result<V, CacheError>>(1);
-                let tx = Arc::new(tx);
-                item.task = Some(Arc::downgrade(&tx));
-
-                let items = self.items.clone();
-                let key = key.clone();
-                // Execute the closure first to avoid sending it across threads
-                let fut = f();
-                tokio::spawn(async move {
-                    let res = fut.await;
-                    {
-                        let mut items = items.lock();
-                        let item = items.entry(key).or_default();
-                        item.task = None;
-
-                        match res {
-                            Ok(value) => {
-                                item.cached.replace((Instant::now(), value.clone()));
-                                let _ = tx.send(Ok(value));
-                            }
-                            Err(e) => {
-                                let _ = tx.send(Err(CacheError(e.to_string())));
-                            }
-                        };
+                self.fetch(item, key.clone(), f)
+            }
+        };
+        self.remove_stored(evicted);
+        match rx.recv().await.map_err(CacheError::new)? {
+            Ok(value) => Ok(Cached {
+                value,
+                expires_in: self.timeout,
+                stale: false,
+            }),
+            Err(err) => {
+                let items = self.items.lock();
+                let stale = items
+                    .get(&key)
+                    .and_then(|item| self.entry(item, self.stale_if_error.unwrap_or_default()));
+                if stale.is_some() {
+                    debug!("failed to fetch {key:?}, serving stale value: {err}");
+                }
+                stale.ok_or(err)
+            }
+        }
+    }
+
+    /// Spawns a task to fetch the value of an item, the result is sent on the returned channel
+    fn fetch<F, E>(
+        &self,
+        item: &mut CacheItem<V>,
+        key: K,
+        f: F,
+    ) -> broadcast::Receiver<Result<V, CacheError>>
+    where
+        F: FnOnce() -> BoxFut<'static, Result<V, E>>,
+        E: Into<CacheError> + 'static,
+    {
+        // Create a new channel to fetch the value
+        let (tx, rx) = broadcast::channel::<Result<V, CacheError>>(1);
+        let tx = Arc::new(tx);
+        item.task = Some(Arc::downgrade(&tx));
+
+        let items = self.items.clone();
+        let store = self.store.clone();
+        let error_timeouts = self.error_timeouts;
+        // Execute the closure first to avoid sending it across threads
+        let fut = f();
+        tokio::spawn(async move {
+            let res = fut.await.map_err(Into::into);
+            {
+                let mut items = items.lock();
+                let item = items.entry(key.clone()).or_default();
+                item.task = None;
+
+                match &res {
+                    Ok(value) => {
+                        item.cached.replace((Instant::now(), value.clone()));
+                        item.hits = 0;
+                        item.failed = None;
+                    }
+                    // the previous value is kept, so that it can be served while it's not too old
+                    Err(err) => {
+                        let count = item
+                            .failed
+                            .as_ref()
+                            .filter(|failure| {
+                                error_timeouts.is_some_and(|t| t.is_remembered(failure))
+                            })
+                            .map_or(0, |failure| failure.count);
+                        item.failed = Some(Failure {
+                            at: Instant::now(),
+                            error: err.clone(),
+                            count: count + 1,
+                        });
                     }
-                });
-                rx
+                }
+                // nobody waits for background refreshes
+                let _ = tx.send(res.clone());
+            }
+            if let (Some(store), Ok(value)) = (store, res) {
+                store.save(&key, &value).await;
+            }
+        });
+        rx
+    }
+}
+
+/// Subtracts a duration from an instant, clamped to the earliest instant of the monotonic clock,
+/// which usually starts when the machine boots. Values that were stored before a reboot then
+/// count as fetched at boot.
+fn saturating_sub(instant: Instant, duration: Duration) -> Instant {
+    if let Some(earlier) = instant.checked_sub(duration) {
+        return earlier;
+    }
+    // the earliest instant isn't exposed, so search for the longest duration that can be
+    // subtracted
+    let (mut low, mut high) = (Duration::ZERO, duration);
+    while high - low > Duration::from_millis(1) {
+        let mid = low + (high - low) / 2;
+        if instant.checked_sub(mid).is_some() {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    instant - low
+}
+
+/// Directory with a JSON file for each cached value, so that the cache survives restarts
+#[derive(Debug, Clone)]
+pub struct Store {
+    dir: PathBuf,
+}
+
+/// A value in the [`Store`], with the time it was fetched
+#[derive(Serialize, Deserialize)]
+struct Stored<K, V> {
+    key: K,
+    fetched_at: SystemTime,
+    value: V,
+}
+
+impl Store {
+    /// Creates the directory if it doesn't exist
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Files are named by a hash of the key, since keys can contain any character
+    fn path<K: Serialize>(&self, key: &K) -> PathBuf {
+        let key = serde_json::to_vec(key).expect("keys can be serialized");
+        let hash = Sha256::digest(key);
+        let name: String = hash[..16].iter().map(|b| format!("{b:02x}")).collect();
+        self.dir.join(name + ".json")
+    }
+
+    /// Loads all values, files that can't be read (e.g. from an older version) are removed
+    fn load<K: DeserializeOwned, V: DeserializeOwned>(&self) -> Vec<Stored<K, V>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!("failed to read cache dir {}: {err}", self.dir.display());
+                return Vec::new();
+            }
+        };
+        let mut stored = Vec::new();
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
             }
+            let parsed = std::fs::read(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|data| serde_json::from_slice(&data).map_err(|err| err.to_string()));
+            match parsed {
+                Ok(item) => stored.push(item),
+                Err(err) => {
+                    debug!("removing unreadable cache file {}: {err}", path.display());
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+        stored
+    }
+
+    async fn save<K: Serialize, V: Serialize>(&self, key: &K, value: &V) {
+        let stored = Stored {
+            key,
+            fetched_at: SystemTime::now(),
+            value,
+        };
+        let data = serde_json::to_vec(&stored).expect("values can be serialized");
+        let path = self.path(key);
+        // written to a temporary file first, so that a crash can't leave a partial file
+        let tmp_path = path.with_extension("tmp");
+        let res = match tokio::fs::write(&tmp_path, data).await {
+            Ok(()) => tokio::fs::rename(&tmp_path, &path).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            tracing::warn!("failed to write cache file {}: {err}", path.display());
+        }
+    }
+
+    fn remove<K: Serialize>(&self, key: &K) {
+        let _ = std::fs::remove_file(self.path(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn store() {
+        let dir = std::env::temp_dir().join(format!("ytfeed-cache-{}", std::process::id()));
+        let store = Store::new(&dir).unwrap();
+        let cache: Cache<String, u32> = Cache::new(None).with_store(store.clone());
+        let value = cache
+            .get_cached("a".to_string(), || {
+                Box::pin(async { Ok::<_, CacheError>(42) })
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+        // written after the value is sent
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let cache: Cache<String, u32> = Cache::new(None).with_store(store.clone());
+        let value = cache
+            .get_cached("a".to_string(), || {
+                Box::pin(async { Ok::<_, CacheError>(0) })
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 42);
+
+        // timed out values are removed
+        std::thread::sleep(Duration::from_millis(10));
+        let cache: Cache<String, u32> =
+            Cache::new(Some(Duration::from_millis(1))).with_store(store.clone());
+        assert!(cache.items.lock().is_empty());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn store_before_boot() {
+        let dir = std::env::temp_dir().join(format!("ytfeed-cache-boot-{}", std::process::id()));
+        let store = Store::new(&dir).unwrap();
+        // older than the monotonic clock, like values stored before a reboot
+        let age = Duration::from_secs(50 * 365 * 24 * 3600);
+        let stored = Stored {
+            key: "a".to_string(),
+            fetched_at: SystemTime::now() - age,
+            value: 42,
+        };
+        std::fs::write(
+            store.path(&stored.key),
+            serde_json::to_vec(&stored).unwrap(),
+        )
+        .unwrap();
+
+        let cache: Cache<String, u32> = Cache::new(Some(2 * age)).with_store(store.clone());
+        assert_eq!(cache.items.lock()["a"].cached.as_ref().unwrap().1, 42);
+        let cache: Cache<String, u32> = Cache::new(Some(age / 2)).with_store(store);
+        assert!(cache.items.lock().is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saturating_sub_instant() {
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        assert_eq!(saturating_sub(now, Duration::ZERO), now);
+        if let Some(earlier) = now.checked_sub(second) {
+            assert_eq!(saturating_sub(now, second), earlier);
+        }
+        assert!(saturating_sub(now, Duration::MAX) <= now);
+    }
+
+    const ERROR_TIMEOUTS: ErrorTimeouts = ErrorTimeouts {
+        transient: Duration::from_millis(50),
+        max_transient: Duration::from_millis(150),
+        not_found: Duration::from_secs(1),
+    };
+
+    fn fail() -> BoxFut<'static, Result<u32, CacheError>> {
+        Box::pin(async { Err(CacheError::new("failed")) })
+    }
+
+    /// For cached failures, which aren't fetched again
+    fn no_fetch() -> BoxFut<'static, Result<u32, CacheError>> {
+        unreachable!()
+    }
+
+    async fn get(cache: &Cache<u32, u32>, key: u32) -> u32 {
+        cache
+            .get_cached(key, || {
+                Box::pin(async move { Ok::<_, CacheError>(key * 10) })
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn lru() {
+        let cache = Cache::new(None).with_max_items(2);
+        get(&cache, 1).await;
+        get(&cache, 2).await;
+        get(&cache, 1).await;
+        get(&cache, 3).await;
+
+        let mut keys: Vec<u32> = cache.items.lock().keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, [1, 3]);
+    }
+
+    #[tokio::test]
+    async fn sweep() {
+        let cache = Cache::new(Some(Duration::from_millis(50))).with_error_timeouts(ERROR_TIMEOUTS);
+        get(&cache, 1).await;
+        assert!(cache.get_cached(2, fail).await.is_err());
+        assert_eq!(cache.items.lock().len(), 2);
+
+        // the failed fetch is kept while the next failure would back off
+        cache.sweep();
+        assert_eq!(cache.items.lock().len(), 2);
+        tokio::time::sleep(Duration::from_millis(110)).await;
+        cache.sweep();
+        assert!(cache.items.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn stale_while_revalidate() {
+        let cache = Cache::new(Some(Duration::from_millis(50)))
+            .with_stale_while_revalidate(Duration::from_millis(100));
+        get(&cache, 1).await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        // the stale value is returned right away while the new one is fetched
+        let value = cache
+            .get_cached(1, || {
+                Box::pin(async {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    Ok::<_, CacheError>(11)
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(value, 10);
+        cache.sweep();
+        assert_eq!(cache.items.lock().len(), 1);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(get(&cache, 1).await, 11);
+
+        // too old to be served
+        tokio::time::sleep(Duration::from_millis(160)).await;
+        assert_eq!(get(&cache, 1).await, 10);
+    }
+
+    #[tokio::test]
+    async fn refresh() {
+        let cache = Cache::new(Some(Duration::from_millis(100)));
+        get(&cache, 1).await;
+        get(&cache, 1).await;
+        get(&cache, 2).await;
+
+        // the request that fetched the value isn't counted
+        assert!(cache.expiring(Duration::from_millis(10), 1).is_empty());
+        assert_eq!(cache.expiring(Duration::from_millis(100), 1), [1]);
+
+        cache.refresh(1, || Box::pin(async { Ok::<_, CacheError>(11) }));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(cache.expiring(Duration::from_millis(100), 1).is_empty());
+        assert_eq!(get(&cache, 1).await, 11);
+    }
+
+    #[tokio::test]
+    async fn stale_if_error() {
+        let cache = Cache::new(Some(Duration::from_millis(50)))
+            .with_stale_if_error(Duration::from_millis(100))
+            .with_error_timeouts(ERROR_TIMEOUTS);
+        get(&cache, 1).await;
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let cached = cache.get_cached_or_stale(1, fail).await.unwrap();
+        assert_eq!(cached.value, 10);
+        assert!(cached.stale);
+        // the failure is cached, so fetching is not retried until it times out
+        let cached = cache.get_cached_or_stale(1, no_fetch).await;
+        assert!(cached.unwrap().stale);
+
+        // too old to be served
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(cache.get_cached_or_stale(1, fail).await.is_err());
+        assert!(cache.get_cached_or_stale(2, fail).await.is_err());
+    }
+
+    #[test]
+    fn backoff() {
+        let transient = CacheError::new("failed");
+        assert_eq!(
+            ERROR_TIMEOUTS.timeout(&transient, 1),
+            Duration::from_millis(50)
+        );
+        assert_eq!(
+            ERROR_TIMEOUTS.timeout(&transient, 2),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            ERROR_TIMEOUTS.timeout(&transient, 3),
+            Duration::from_millis(150)
+        );
+        assert_eq!(
+            ERROR_TIMEOUTS.timeout(&transient, 100),
+            Duration::from_millis(150)
+        );
+        let not_found = CacheError::not_found("missing");
+        assert_eq!(
+            ERROR_TIMEOUTS.timeout(&not_found, 1),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn error_timeouts() {
+        let cache = Cache::new(None).with_error_timeouts(ERROR_TIMEOUTS);
+        assert!(cache.get_cached(1, fail).await.is_err());
+        assert!(cache.get_cached(1, no_fetch).await.is_err());
+
+        // the second failure in a row is cached twice as long
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cache.get_cached(1, fail).await.is_err());
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cache.get_cached(1, no_fetch).await.is_err());
+
+        let not_found = || -> BoxFut<'static, Result<u32, CacheError>> {
+            Box::pin(async { Err(CacheError::not_found("missing")) })
         };
-        rx.recv().await.map_err(|e| CacheError(e.to_string()))?
+        assert!(cache.get_cached(2, not_found).await.is_err());
+        tokio::time::sleep(Duration::from_millis(160)).await;
+        assert!(cache.get_cached(2, no_fetch).await.is_err());
+        // the transient failure has timed out
+        assert_eq!(get(&cache, 1).await, 10);
     }
 }
@@ -0,0 +1,134 @@
+//! Conditional GET support, so that feed readers that poll often don't download unchanged feeds
+
+use axum::http::{
+    header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    response::Builder,
+    HeaderMap,
+};
+use chrono::{DateTime, TimeZone};
+use sha2::{Digest, Sha256};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Validators of a rendered feed
+#[derive(Debug, Clone)]
+pub struct Validators {
+    /// Strong entity tag, including the quotes
+    etag: String,
+    /// Truncated to seconds, the precision of HTTP dates
+    last_modified: Option<SystemTime>,
+    max_age: Option<Duration>,
+}
+
+impl Validators {
+    pub fn new<Tz: TimeZone>(
+        body: &str,
+        last_modified: Option<DateTime<Tz>>,
+        max_age: Option<Duration>,
+    ) -> Self {
+        let hash = Sha256::digest(body.as_bytes());
+        let etag = format!(
+            "\"{}\"",
+            hash[..16]
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<String>()
+        );
+        let last_modified = last_modified
+            .and_then(|date| u64::try_from(date.timestamp()).ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        Self {
+            etag,
+            last_modified,
+            max_age,
+        }
+    }
+
+    /// Checks the request preconditions, `If-None-Match` takes precedence over
+    /// `If-Modified-Since` (RFC 9110, section 13.2.2)
+    pub fn is_not_modified(&self, headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+            let Ok(if_none_match) = if_none_match.to_str() else {
+                return false;
+            };
+            // weak comparison, a weak tag of the same feed also matches
+            return if_none_match
+                .split(',')
+                .map(|tag| tag.trim())
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        }
+        let if_modified_since = headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+        match (self.last_modified, if_modified_since) {
+            (Some(last_modified), Some(since)) => last_modified <= since,
+            _ => false,
+        }
+    }
+
+    /// Adds the validators and caching headers to a response
+    pub fn headers(&self, mut builder: Builder) -> Builder {
+        builder = builder.header(ETAG, &self.etag);
+        if let Some(last_modified) = self.last_modified {
+            builder = builder.header(LAST_MODIFIED, httpdate::fmt_http_date(last_modified));
+        }
+        if let Some(max_age) = self.max_age {
+            builder = builder.header(CACHE_CONTROL, format!("max-age={}", max_age.as_secs()));
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(name: axum::http::HeaderName, value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn conditional() {
+        let updated = DateTime::parse_from_rfc3339("2026-10-15T10:00:00.500+00:00").unwrap();
+        let validators = Validators::new("<feed/>", Some(updated), Some(Duration::from_secs(42)));
+        let etag = validators.etag.clone();
+        assert_eq!(etag.len(), 34);
+        assert_eq!(
+            Validators::new("<feed/>", None::<DateTime<chrono::Utc>>, None).etag,
+            etag
+        );
+        assert_ne!(
+            Validators::new("<feed />", None::<DateTime<chrono::Utc>>, None).etag,
+            etag
+        );
+
+        assert!(!validators.is_not_modified(&HeaderMap::new()));
+        let mut if_none_match = HeaderMap::new();
+        if_none_match.insert(
+            IF_NONE_MATCH,
+            format!("\"other\", W/{etag}").parse().unwrap(),
+        );
+        assert!(validators.is_not_modified(&if_none_match));
+        assert!(!validators.is_not_modified(&headers(IF_NONE_MATCH, "\"other\"")));
+        assert!(validators.is_not_modified(&headers(IF_NONE_MATCH, "*")));
+
+        let since = |date| headers(IF_MODIFIED_SINCE, date);
+        assert!(validators.is_not_modified(&since("Thu, 15 Oct 2026 10:00:00 GMT")));
+        assert!(validators.is_not_modified(&since("Fri, 16 Oct 2026 08:00:00 GMT")));
+        assert!(!validators.is_not_modified(&since("Thu, 15 Oct 2026 09:59:59 GMT")));
+        assert!(!validators.is_not_modified(&since("yesterday")));
+        // a matching date is ignored when the entity tag doesn't match
+        let mut both = since("Fri, 16 Oct 2026 08:00:00 GMT");
+        both.insert(IF_NONE_MATCH, HeaderValue::from_static("\"other\""));
+        assert!(!validators.is_not_modified(&both));
+
+        let response = validators.headers(Builder::new()).body(()).unwrap();
+        let headers = response.headers();
+        assert_eq!(headers[ETAG], etag.as_str());
+        assert_eq!(headers[LAST_MODIFIED], "Thu, 15 Oct 2026 10:00:00 GMT");
+        assert_eq!(headers[CACHE_CONTROL], "max-age=42");
+    }
+}
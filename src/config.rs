@@ -0,0 +1,502 @@
+//! Configuration from command line flags and an optional TOML file, flags override the file
+
+use crate::{
+    cache::ErrorTimeouts,
+    extractor::{Backend, Instances, VideoKind},
+    feed::Format,
+    filter::{self, Filter, AD_KEYWORDS},
+    proxy::FeedSource,
+    takeout::ImportArgs,
+};
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand};
+use reqwest::{header::HeaderValue, Url};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const DEFAULT_SOCKET: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8000);
+const DEFAULT_BASE_URL: &str = "http://localhost:8000/";
+const DEFAULT_CACHE_TIMEOUT: u64 = 300;
+const DEFAULT_CACHE_MAX_ITEMS: usize = 10_000;
+const DEFAULT_CACHE_STALE_IF_ERROR: u64 = 24 * 60 * 60;
+const DEFAULT_CACHE_ERROR_TIMEOUT: u64 = 30;
+const DEFAULT_CACHE_MAX_ERROR_TIMEOUT: u64 = 30 * 60;
+const DEFAULT_CACHE_NOT_FOUND_TIMEOUT: u64 = 24 * 60 * 60;
+const DEFAULT_CLIENT_TIMEOUT: u64 = 10;
+const DEFAULT_MAX_VIDEOS: usize = 30;
+
+/// Command line flags, which are all optional so that they only override the config file when
+/// they are set
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about)]
+struct Cli {
+    /// TOML configuration file, see `config.example.toml`
+    #[arg(long = "config", env = "YTFEED_CONFIG")]
+    config: Option<PathBuf>,
+    /// Socket address [default: 0.0.0.0:8000]
+    #[arg(short = 's', long = "socket")]
+    socket_address: Option<SocketAddr>,
+    /// How long to keep feeds cached (in seconds) [default: 300]
+    #[arg(short = 'c', long = "cache")]
+    cache_timeout: Option<u64>,
+    /// Directory to store cached feeds in, so that they are kept after a restart
+    #[arg(long = "cache_dir")]
+    cache_dir: Option<PathBuf>,
+    /// Base URL used in the feed [default: http://localhost:8000/]
+    #[arg(short = 'b', long = "base_url")]
+    base_url: Option<String>,
+    /// Maximum number of videos to scrape from each channel tab, the first page has about 30
+    /// videos and each following page requires another request [default: 30]
+    #[arg(short = 'n', long = "max_videos")]
+    max_videos: Option<usize>,
+    /// Backend used to extract channel data, the other backends are used as fallback
+    /// [default: html]
+    #[arg(short = 'e', long = "extractor", value_enum)]
+    extractor: Option<Backend>,
+    /// Base URL of an Invidious instance to use as extractor backend
+    #[arg(long = "invidious")]
+    invidious: Option<String>,
+    /// Base URL of the API of a Piped instance to use as extractor backend
+    #[arg(long = "piped")]
+    piped: Option<String>,
+    /// Directory with audio files named by video id, served as podcast enclosures
+    #[arg(short = 'm', long = "media_dir")]
+    media_dir: Option<PathBuf>,
+    /// Named group of feeds that are merged into one feed at `/group/NAME`, e.g.
+    /// `music=@a,@b,playlist/PL...`
+    #[arg(short = 'g', long = "group", value_parser = parse_group)]
+    groups: Vec<(String, Vec<String>)>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Converts the `subscriptions.csv` of a Google Takeout export to an OPML file or a group of
+    /// the config file, instead of starting the server
+    Import(ImportArgs),
+}
+
+fn parse_group(s: &str) -> Result<(String, Vec<String>), String> {
+    let (name, sources) = s
+        .split_once('=')
+        .ok_or("expected a name and feeds, e.g. 'music=@a,@b'")?;
+    let sources = sources.split(',').map(str::to_string).collect();
+    Ok((name.to_string(), sources))
+}
+
+/// Contents of the config file
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct FileConfig {
+    socket: Option<SocketAddr>,
+    base_url: Option<String>,
+    max_videos: Option<usize>,
+    extractor: Option<Backend>,
+    invidious: Option<String>,
+    piped: Option<String>,
+    media_dir: Option<PathBuf>,
+    #[serde(default)]
+    cache: CacheConfig,
+    #[serde(default)]
+    client: ClientConfig,
+    /// Filter options used when a request doesn't set them, with the query parameter names
+    #[serde(default)]
+    filter: FilterConfig,
+    #[serde(default)]
+    ads: AdsConfig,
+    #[serde(default)]
+    groups: HashMap<String, Vec<String>>,
+}
+
+/// Same options as [`Filter`], but unknown names are rejected instead of ignored, since a query
+/// string can have other parameters
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct FilterConfig {
+    d: Option<u64>,
+    v: Option<u64>,
+    l: Option<u64>,
+    #[serde(default)]
+    lvr: bool,
+    #[serde(default)]
+    k: Vec<VideoKind>,
+    #[serde(default)]
+    b: bool,
+    format: Option<Format>,
+}
+
+impl From<FilterConfig> for Filter {
+    fn from(config: FilterConfig) -> Self {
+        Filter {
+            duration: config.d,
+            views: config.v,
+            likes: config.l,
+            like_view_ratio: config.lvr,
+            kinds: config.k,
+            backfill: config.b,
+            format: config.format,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct CacheConfig {
+    /// In seconds
+    timeout: Option<u64>,
+    dir: Option<PathBuf>,
+    max_items: Option<usize>,
+    /// In seconds
+    stale_while_revalidate: Option<u64>,
+    /// In seconds
+    stale_if_error: Option<u64>,
+    /// In seconds
+    error_timeout: Option<u64>,
+    /// In seconds
+    max_error_timeout: Option<u64>,
+    /// In seconds
+    not_found_timeout: Option<u64>,
+    refresh_min_hits: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ClientConfig {
+    /// In seconds
+    timeout: Option<u64>,
+    user_agent: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct AdsConfig {
+    /// Whether to use the built-in keywords
+    #[serde(default = "default_true")]
+    default_keywords: bool,
+    /// Additional keywords, lines of descriptions that contain one are removed
+    #[serde(default)]
+    keywords: Vec<String>,
+}
+
+impl Default for AdsConfig {
+    fn default() -> Self {
+        Self {
+            default_keywords: true,
+            keywords: Vec::new(),
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl FileConfig {
+    fn read(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|err| format!("failed to read config file '{}': {err}", path.display()))?;
+        toml::from_str(&content)
+            .map_err(|err| format!("invalid config file '{}': {err}", path.display()))
+    }
+}
+
+/// Validated configuration
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub socket_address: SocketAddr,
+    /// Always ends with a '/'
+    pub base_url: String,
+    pub cache_timeout: Duration,
+    pub cache_dir: Option<PathBuf>,
+    /// Maximum number of items in each cache
+    pub cache_max_items: usize,
+    /// How long expired feeds are served while they are refreshed
+    pub cache_stale_while_revalidate: Option<Duration>,
+    /// How long expired feeds are served when they can't be refreshed
+    pub cache_stale_if_error: Option<Duration>,
+    pub cache_error_timeouts: ErrorTimeouts,
+    /// Requests after which a feed is refreshed before it expires
+    pub cache_refresh_min_hits: Option<u32>,
+    pub client_timeout: Duration,
+    pub user_agent: Option<String>,
+    pub max_videos: usize,
+    pub extractor: Backend,
+    pub instances: Instances,
+    pub media_dir: Option<PathBuf>,
+    pub default_filter: Filter,
+    pub ad_keywords: Vec<String>,
+    pub groups: HashMap<String, Vec<FeedSource>>,
+    pub command: Option<Command>,
+}
+
+impl Config {
+    /// Parses the flags and config file, exits with an error message if they are invalid
+    pub fn load() -> Self {
+        Self::from_cli(Cli::parse())
+            .unwrap_or_else(|err| Cli::command().error(ErrorKind::InvalidValue, err).exit())
+    }
+
+    /// Exits with a usage error, for errors that are found after loading
+    pub fn exit_with_error(kind: ErrorKind, message: impl std::fmt::Display) -> ! {
+        Cli::command().error(kind, message).exit()
+    }
+
+    fn from_cli(cli: Cli) -> Result<Self, String> {
+        let file = match &cli.config {
+            Some(path) => FileConfig::read(path)?,
+            None => FileConfig::default(),
+        };
+
+        let mut base_url = cli
+            .base_url
+            .or(file.base_url)
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        validate_url("base URL", &base_url)?;
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+
+        let cache_max_items = file.cache.max_items.unwrap_or(DEFAULT_CACHE_MAX_ITEMS);
+        if cache_max_items == 0 {
+            return Err("the cache must have room for at least one item".to_string());
+        }
+        if file.cache.refresh_min_hits == Some(0) {
+            return Err("feeds must be requested at least once to be refreshed".to_string());
+        }
+
+        let client_timeout = file.client.timeout.unwrap_or(DEFAULT_CLIENT_TIMEOUT);
+        if client_timeout == 0 {
+            return Err("the client timeout must be at least one second".to_string());
+        }
+        if let Some(user_agent) = &file.client.user_agent {
+            HeaderValue::from_str(user_agent)
+                .map_err(|_| format!("invalid user agent '{user_agent}'"))?;
+        }
+
+        let max_videos = cli
+            .max_videos
+            .or(file.max_videos)
+            .unwrap_or(DEFAULT_MAX_VIDEOS);
+        if max_videos == 0 {
+            return Err("the maximum number of videos must be at least one".to_string());
+        }
+
+        let instances = Instances {
+            invidious: cli.invidious.or(file.invidious),
+            piped: cli.piped.or(file.piped),
+        };
+        for (name, url) in [
+            ("Invidious URL", &instances.invidious),
+            ("Piped URL", &instances.piped),
+        ] {
+            if let Some(url) = url {
+                validate_url(name, url)?;
+            }
+        }
+
+        let media_dir = cli.media_dir.or(file.media_dir);
+        if let Some(dir) = &media_dir {
+            if !dir.is_dir() {
+                return Err(format!("media directory '{}' doesn't exist", dir.display()));
+            }
+        }
+
+        let mut ad_keywords: Vec<String> = if file.ads.default_keywords {
+            AD_KEYWORDS.iter().map(|kw| kw.to_string()).collect()
+        } else {
+            Vec::new()
+        };
+        // matched against normalized lines
+        ad_keywords.extend(file.ads.keywords.iter().map(|kw| filter::normalize(kw)));
+
+        // groups from flags replace groups with the same name from the file
+        let mut groups = HashMap::new();
+        for (name, sources) in file.groups.into_iter().chain(cli.groups) {
+            if name.is_empty() || name.contains('/') {
+                return Err(format!("invalid group name '{name}'"));
+            }
+            if sources.is_empty() {
+                return Err(format!("group '{name}' has no feeds"));
+            }
+            let sources = sources
+                .iter()
+                .map(|source| source.parse())
+                .collect::<Result<Vec<FeedSource>, _>>()
+                .map_err(|err| format!("group '{name}': {err}"))?;
+            groups.insert(name, sources);
+        }
+
+        Ok(Self {
+            socket_address: cli.socket_address.or(file.socket).unwrap_or(DEFAULT_SOCKET),
+            base_url,
+            cache_timeout: Duration::from_secs(
+                cli.cache_timeout
+                    .or(file.cache.timeout)
+                    .unwrap_or(DEFAULT_CACHE_TIMEOUT),
+            ),
+            cache_dir: cli.cache_dir.or(file.cache.dir),
+            cache_max_items,
+            cache_stale_while_revalidate: file
+                .cache
+                .stale_while_revalidate
+                .filter(|&secs| secs > 0)
+                .map(Duration::from_secs),
+            cache_stale_if_error: Some(
+                file.cache
+                    .stale_if_error
+                    .unwrap_or(DEFAULT_CACHE_STALE_IF_ERROR),
+            )
+            .filter(|&secs| secs > 0)
+            .map(Duration::from_secs),
+            cache_error_timeouts: ErrorTimeouts {
+                transient: Duration::from_secs(
+                    file.cache
+                        .error_timeout
+                        .unwrap_or(DEFAULT_CACHE_ERROR_TIMEOUT),
+                ),
+                max_transient: Duration::from_secs(
+                    file.cache
+                        .max_error_timeout
+                        .unwrap_or(DEFAULT_CACHE_MAX_ERROR_TIMEOUT),
+                ),
+                not_found: Duration::from_secs(
+                    file.cache
+                        .not_found_timeout
+                        .unwrap_or(DEFAULT_CACHE_NOT_FOUND_TIMEOUT),
+                ),
+            },
+            cache_refresh_min_hits: file.cache.refresh_min_hits,
+            client_timeout: Duration::from_secs(client_timeout),
+            user_agent: file.client.user_agent,
+            max_videos,
+            extractor: cli.extractor.or(file.extractor).unwrap_or(Backend::Html),
+            instances,
+            media_dir,
+            default_filter: file.filter.into(),
+            ad_keywords,
+            groups,
+            command: cli.command,
+        })
+    }
+}
+
+fn validate_url(name: &str, url: &str) -> Result<(), String> {
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") => Ok(()),
+        _ => Err(format!("invalid {name} '{url}', expected an http(s) URL")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn load(file: &str, args: &[&str]) -> Result<Config, String> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "ytfeed-config-{}-{}.toml",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, file).unwrap();
+        let path_arg = path.to_str().unwrap();
+        let cli = Cli::try_parse_from(["ytfeed", "--config", path_arg].iter().chain(args))
+            .map_err(|err| err.to_string())?;
+        let config = Config::from_cli(cli);
+        std::fs::remove_file(path).unwrap();
+        config
+    }
+
+    #[test]
+    fn file_and_flags() {
+        let file = r#"
+            socket = "127.0.0.1:9000"
+            base_url = "https://feeds.example.com"
+            max_videos = 60
+
+            [cache]
+            timeout = 600
+            stale_while_revalidate = 120
+            not_found_timeout = 3600
+
+            [client]
+            timeout = 20
+            user_agent = "ytfeed"
+
+            [filter]
+            d = 600
+            k = ["video", "archived"]
+
+            [ads]
+            keywords = ["Patreon"]
+
+            [groups]
+            music = ["@a", "playlist/PL1"]
+        "#;
+        let config = load(file, &["-c", "60", "-g", "news=@b"]).unwrap();
+
+        assert_eq!(config.socket_address.to_string(), "127.0.0.1:9000");
+        assert_eq!(config.base_url, "https://feeds.example.com/");
+        assert_eq!(config.cache_timeout, Duration::from_secs(60));
+        assert_eq!(
+            config.cache_stale_while_revalidate,
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            config.cache_error_timeouts.not_found,
+            Duration::from_secs(3600)
+        );
+        assert_eq!(
+            config.cache_error_timeouts.transient,
+            Duration::from_secs(DEFAULT_CACHE_ERROR_TIMEOUT)
+        );
+        assert_eq!(config.client_timeout, Duration::from_secs(20));
+        assert_eq!(config.max_videos, 60);
+        assert_eq!(config.default_filter.duration, Some(600));
+        assert_eq!(config.default_filter.kinds.len(), 2);
+        assert!(config.ad_keywords.contains(&"patreon".to_string()));
+        assert!(config.ad_keywords.len() > 1);
+        assert_eq!(
+            config.groups["music"],
+            [
+                FeedSource::Handle("a".to_string()),
+                FeedSource::Playlist("PL1".to_string())
+            ]
+        );
+        assert_eq!(config.groups["news"].len(), 1);
+
+        let config = load("", &[]).unwrap();
+        assert_eq!(config.socket_address, DEFAULT_SOCKET);
+        assert_eq!(config.base_url, DEFAULT_BASE_URL);
+        assert_eq!(
+            config.cache_stale_if_error,
+            Some(Duration::from_secs(DEFAULT_CACHE_STALE_IF_ERROR))
+        );
+    }
+
+    #[test]
+    fn example() {
+        let config = load(include_str!("../config.example.toml"), &[]).unwrap();
+        assert_eq!(config.groups["music"].len(), 3);
+    }
+
+    #[test]
+    fn invalid() {
+        let error = |file| load(file, &[]).unwrap_err();
+        assert!(error("unknown = 1").contains("unknown field"));
+        assert!(error("[filter]\nduration = 600").contains("unknown field"));
+        assert!(error("[cache]\ntimeout = \"long\"").contains("invalid type"));
+        assert!(error("base_url = \"localhost\"").contains("invalid base URL"));
+        assert!(error("[client]\ntimeout = 0").contains("client timeout"));
+        assert!(error("[cache]\nrefresh_min_hits = 0").contains("refreshed"));
+        assert!(error("[groups]\nmusic = [\"a\"]").contains("group 'music'"));
+        assert!(error("[groups]\nmusic = []").contains("no feeds"));
+        assert!(error("media_dir = \"/nonexistent\"").contains("media directory"));
+    }
+}
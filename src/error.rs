@@ -4,6 +4,7 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,21 +12,72 @@ pub enum Error {
     #[error("failed HTTP request: {0}")]
     HttpRequest(reqwest::Error),
     #[error("scraping: {0}")]
-    Scrape(&'static str),
+    Scrape(#[from] ScrapeError),
     #[error("cache: {0}")]
     Cache(#[from] CacheError),
     #[error("channel '{0}' not found")]
     ChannelNotFound(String),
+    #[error("playlist '{0}' not found")]
+    PlaylistNotFound(String),
+    #[error("group '{0}' not found")]
+    GroupNotFound(String),
+    #[error("invalid feed '{0}', expected a path like '@handle' or 'channel/UC...'")]
+    InvalidSource(String),
+    #[error("no feeds to merge, add them with the 'h' query parameter")]
+    NothingToMerge,
+    #[error("too many feeds to merge, at most {0} are allowed")]
+    TooManyFeeds(usize),
     #[error("Failed to proxy feed '{0}'")]
     Proxy(String),
+    #[error("podcasts are not enabled, they require a media directory")]
+    NoMediaDir,
+    #[error("invalid OPML: {0}")]
+    Opml(#[from] quick_xml::Error),
     #[error("JSON parse: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("IO: {0}")]
+    Io(#[from] std::io::Error),
     #[error("url encode: {0}")]
     UrlEncode(#[from] serde_html_form::ser::Error),
     #[error("feed parse: {0}")]
     Feed(#[from] atom_syndication::Error),
 }
 
+/// Data from YouTube or an instance API that doesn't have the expected shape
+#[derive(Error, Debug)]
+pub enum ScrapeError {
+    /// A required value is missing, the path is a JSON pointer or the name of an XML element
+    #[error("missing '{path}'")]
+    Missing { path: String },
+    /// A value has an unexpected type or can't be parsed
+    #[error("unexpected value at '{path}': {value}")]
+    Unexpected { path: String, value: String },
+    #[error("{0}")]
+    Other(&'static str),
+}
+
+impl ScrapeError {
+    pub fn missing(path: impl Into<String>) -> Self {
+        ScrapeError::Missing { path: path.into() }
+    }
+
+    /// Values are truncated, so that a whole page doesn't end up in the logs
+    pub fn unexpected(path: impl Into<String>, value: impl fmt::Display) -> Self {
+        const MAX_LEN: usize = 100;
+        let mut value = value.to_string();
+        if let Some((idx, _)) = value.char_indices().nth(MAX_LEN) {
+            value.truncate(idx);
+            value.push_str("...");
+        }
+        ScrapeError::Unexpected {
+            path: path.into(),
+            value,
+        }
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(err: reqwest::Error) -> Self {
         // Log detailed error information
@@ -50,6 +102,17 @@ impl From<reqwest::Error> for Error {
     }
 }
 
+impl From<Error> for CacheError {
+    /// Missing channels and playlists are cached longer than other errors
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Cache(err) => err,
+            Error::ChannelNotFound(_) | Error::PlaylistNotFound(_) => CacheError::not_found(err),
+            err => CacheError::new(err),
+        }
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let (status, log) = match self {
@@ -58,9 +121,18 @@ impl IntoResponse for Error {
                 (StatusCode::BAD_GATEWAY, true)
             }
             Error::Scrape(_) | Error::Cache(_) => (StatusCode::INTERNAL_SERVER_ERROR, true),
-            Error::UrlEncode(_) => (StatusCode::INTERNAL_SERVER_ERROR, true),
+            Error::UrlEncode(_) | Error::Csv(_) | Error::Io(_) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, true)
+            }
             // Other
-            Error::ChannelNotFound(_) => (StatusCode::NOT_FOUND, false),
+            Error::ChannelNotFound(_)
+            | Error::PlaylistNotFound(_)
+            | Error::GroupNotFound(_)
+            | Error::NoMediaDir => (StatusCode::NOT_FOUND, false),
+            Error::InvalidSource(_)
+            | Error::NothingToMerge
+            | Error::TooManyFeeds(_)
+            | Error::Opml(_) => (StatusCode::BAD_REQUEST, false),
             Error::Proxy(_) => (StatusCode::BAD_GATEWAY, false),
         };
         let msg = self.to_string();
@@ -0,0 +1,127 @@
+use super::{
+    extract_channel_pages, extract_playlist_page, has_error_alert, str_at, Extraction, Extractor,
+    Tab,
+};
+use crate::{
+    cache::BoxFut,
+    error::{Error, ScrapeError},
+};
+use reqwest::{Client, StatusCode};
+use scraper::{Html, Selector};
+use serde_json::Value;
+
+/// Extracts data by scraping the `ytInitialData` embedded in the YouTube website
+pub struct HtmlExtractor {
+    /// Base URL of the website, [`YOUTUBE_URL`](super::YOUTUBE_URL) unless testing
+    base_url: String,
+    client: Client,
+}
+
+impl HtmlExtractor {
+    pub fn new(base_url: &str, client: Client) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+        }
+    }
+
+    async fn channel(
+        &self,
+        channel_path: &str,
+        tabs: &[Tab],
+        max_videos: usize,
+    ) -> Result<Extraction, Error> {
+        let not_found = || Error::ChannelNotFound(channel_path.to_string());
+        let mut pages = Vec::new();
+        for tab in tabs {
+            let tab_url = format!("{}/{}/{}", self.base_url, channel_path, tab.path());
+            let data = fetch_initial_data(&tab_url, &self.client)
+                .await?
+                .filter(|data| !has_error_alert(data))
+                .ok_or_else(not_found)?;
+            pages.push((*tab, data));
+        }
+        extract_channel_pages(pages, max_videos, &self.base_url, &self.client).await
+    }
+
+    async fn channel_id(&self, channel_path: &str) -> Result<String, Error> {
+        let channel_url = format!("{}/{}", self.base_url, channel_path);
+        let not_found = || Error::ChannelNotFound(channel_path.to_string());
+        let data = fetch_initial_data(&channel_url, &self.client)
+            .await?
+            .filter(|data| !has_error_alert(data))
+            .ok_or_else(not_found)?;
+        let channel_id = str_at(&data, "/metadata/channelMetadataRenderer/externalId")?;
+        Ok(channel_id.to_string())
+    }
+
+    async fn playlist(&self, playlist_id: &str) -> Result<Extraction, Error> {
+        let playlist_url = format!("{}/playlist?list={}", self.base_url, playlist_id);
+        let data = fetch_initial_data(&playlist_url, &self.client)
+            .await?
+            .ok_or_else(|| Error::PlaylistNotFound(playlist_id.to_string()))?;
+        extract_playlist_page(playlist_id, &data)
+    }
+}
+
+impl Extractor for HtmlExtractor {
+    fn name(&self) -> &'static str {
+        "html"
+    }
+
+    fn extract_channel<'a>(
+        &'a self,
+        channel_path: &'a str,
+        tabs: &'a [Tab],
+        max_videos: usize,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.channel(channel_path, tabs, max_videos))
+    }
+
+    fn extract_channel_id<'a>(
+        &'a self,
+        channel_path: &'a str,
+    ) -> BoxFut<'a, Result<String, Error>> {
+        Box::pin(self.channel_id(channel_path))
+    }
+
+    fn extract_playlist<'a>(
+        &'a self,
+        playlist_id: &'a str,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.playlist(playlist_id))
+    }
+}
+
+/// Fetches a YouTube page and parses its `ytInitialData`, returns `None` if the page doesn't exist
+///
+/// Consent, bot check and rate limit pages don't have the data, which is a scrape error rather
+/// than a missing channel, so that it is retried.
+async fn fetch_initial_data(url: &str, client: &Client) -> Result<Option<Value>, Error> {
+    tracing::debug!("scraping data from '{}'", url);
+    let response = client
+        .get(url)
+        .header("Accept-Language", "en") // to get data in English locale formats
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let text = response.error_for_status()?.text().await?;
+    let html = Html::parse_fragment(&text);
+    let script_selector = Selector::parse("script").unwrap();
+    for element in html.select(&script_selector) {
+        let script = element.inner_html();
+        let script = script.trim();
+        if !script.starts_with("var ytInitialData") {
+            continue;
+        }
+        let json = script
+            .strip_prefix("var ytInitialData = ")
+            .ok_or(ScrapeError::Other("failed to strip prefix"))?
+            .strip_suffix(';')
+            .ok_or(ScrapeError::Other("failed to strip suffix"))?;
+        return Ok(Some(serde_json::from_str(json)?));
+    }
+    Err(ScrapeError::missing("ytInitialData").into())
+}
@@ -0,0 +1,166 @@
+use super::{
+    extract_channel_pages, extract_playlist_page, has_error_alert, str_at, Extraction, Extractor,
+    Tab, YOUTUBE_URL,
+};
+use crate::{
+    cache::BoxFut,
+    error::{Error, ScrapeError},
+};
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+
+/// Client version sent to the InnerTube API if the version of the page is not known
+pub const CLIENT_VERSION: &str = "2.20250101.00.00";
+
+/// Extracts data from the InnerTube JSON API that is used by the YouTube website itself
+pub struct InnerTubeExtractor {
+    /// Base URL of the website, [`YOUTUBE_URL`] unless testing
+    base_url: String,
+    client: Client,
+}
+
+impl InnerTubeExtractor {
+    pub fn new(base_url: &str, client: Client) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+        }
+    }
+
+    async fn channel(
+        &self,
+        channel_path: &str,
+        tabs: &[Tab],
+        max_videos: usize,
+    ) -> Result<Extraction, Error> {
+        let not_found = || Error::ChannelNotFound(channel_path.to_string());
+        let browse_id = self.channel_id(channel_path).await?;
+        let mut pages = Vec::new();
+        for tab in tabs {
+            let body = serde_json::json!({
+                "context": context(CLIENT_VERSION),
+                "browseId": browse_id,
+                "params": browse_params(*tab),
+            });
+            let data = post(&self.base_url, "browse", body, &self.client)
+                .await?
+                .filter(|data| !has_error_alert(data))
+                .ok_or_else(not_found)?;
+            pages.push((*tab, data));
+        }
+        extract_channel_pages(pages, max_videos, &self.base_url, &self.client).await
+    }
+
+    async fn channel_id(&self, channel_path: &str) -> Result<String, Error> {
+        if let Some(channel_id) = channel_path.strip_prefix("channel/") {
+            return Ok(channel_id.to_string());
+        }
+        let not_found = || Error::ChannelNotFound(channel_path.to_string());
+        let body = serde_json::json!({
+            "context": context(CLIENT_VERSION),
+            "url": format!("{}/{}", YOUTUBE_URL, channel_path),
+        });
+        let data = post(&self.base_url, "navigation/resolve_url", body, &self.client)
+            .await?
+            .ok_or_else(not_found)?;
+        let channel_id = str_at(&data, "/endpoint/browseEndpoint/browseId")?;
+        Ok(channel_id.to_string())
+    }
+
+    async fn playlist(&self, playlist_id: &str) -> Result<Extraction, Error> {
+        let body = serde_json::json!({
+            "context": context(CLIENT_VERSION),
+            "browseId": format!("VL{}", playlist_id),
+        });
+        let data = post(&self.base_url, "browse", body, &self.client)
+            .await?
+            .ok_or_else(|| Error::PlaylistNotFound(playlist_id.to_string()))?;
+        extract_playlist_page(playlist_id, &data)
+    }
+}
+
+impl Extractor for InnerTubeExtractor {
+    fn name(&self) -> &'static str {
+        "innertube"
+    }
+
+    fn extract_channel<'a>(
+        &'a self,
+        channel_path: &'a str,
+        tabs: &'a [Tab],
+        max_videos: usize,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.channel(channel_path, tabs, max_videos))
+    }
+
+    fn extract_channel_id<'a>(
+        &'a self,
+        channel_path: &'a str,
+    ) -> BoxFut<'a, Result<String, Error>> {
+        Box::pin(self.channel_id(channel_path))
+    }
+
+    fn extract_playlist<'a>(
+        &'a self,
+        playlist_id: &'a str,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.playlist(playlist_id))
+    }
+}
+
+/// Fetches the next page of items using the `browse` endpoint
+pub async fn fetch_continuation(
+    token: &str,
+    client_version: &str,
+    base_url: &str,
+    client: &Client,
+) -> Result<Value, Error> {
+    let body = serde_json::json!({
+        "context": context(client_version),
+        "continuation": token,
+    });
+    post(base_url, "browse", body, client)
+        .await?
+        .ok_or(ScrapeError::Other("continuation not found").into())
+}
+
+/// Sends a request to an InnerTube endpoint, returns `None` if it responds with a 404
+async fn post(
+    base_url: &str,
+    endpoint: &str,
+    body: Value,
+    client: &Client,
+) -> Result<Option<Value>, Error> {
+    let url = format!("{}/youtubei/v1/{}?prettyPrint=false", base_url, endpoint);
+    tracing::debug!("querying '{}'", url);
+    let response = client
+        .post(&url)
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let text = response.error_for_status()?.text().await?;
+    Ok(Some(serde_json::from_str(&text)?))
+}
+
+fn context(client_version: &str) -> Value {
+    serde_json::json!({
+        "client": {
+            "clientName": "WEB",
+            "clientVersion": client_version,
+            "hl": "en", // to get data in English locale formats
+        }
+    })
+}
+
+/// Parameters that select a tab of a channel in a `browse` request
+fn browse_params(tab: Tab) -> &'static str {
+    match tab {
+        Tab::Videos => "EgZ2aWRlb3PyBgQKAjoA",
+        Tab::Streams => "EgdzdHJlYW1z8gYECgJ6AA==",
+        Tab::Shorts => "EgZzaG9ydHPyBgUKA5oBAA==",
+    }
+}
@@ -0,0 +1,321 @@
+use super::{
+    from_timestamp, get_json, max_continuations, skip_item, str_at, Extraction, Extractor, Tab,
+    VideoInfo, VideoKind,
+};
+use crate::{
+    cache::BoxFut,
+    error::{Error, ScrapeError},
+    feed::Channel,
+};
+use reqwest::Client;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Extracts data from the API of an Invidious instance
+pub struct InvidiousExtractor {
+    base_url: String,
+    client: Client,
+}
+
+impl InvidiousExtractor {
+    pub fn new(base_url: &str, client: Client) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+        }
+    }
+
+    async fn channel(
+        &self,
+        channel_path: &str,
+        tabs: &[Tab],
+        max_videos: usize,
+    ) -> Result<Extraction, Error> {
+        let not_found = || Error::ChannelNotFound(channel_path.to_string());
+        let channel_id = self.channel_id(channel_path).await?;
+        let channel_url = format!("{}/api/v1/channels/{}", self.base_url, channel_id);
+        let data = get_json(&format!("{channel_url}?fields=author"), &self.client)
+            .await?
+            .ok_or_else(not_found)?;
+        let channel = Channel {
+            title: str_at(&data, "/author")?.to_string(),
+            url: format!("https://www.youtube.com/channel/{channel_id}"),
+            id: channel_id,
+        };
+        let mut videos: Vec<VideoInfo> = Vec::new();
+        let mut skipped = 0;
+        let max_pages = max_continuations(max_videos);
+        for tab in tabs {
+            let mut tab_videos = Vec::new();
+            let mut continuation = None;
+            let mut pages = 0;
+            loop {
+                let mut tab_url = format!("{}/{}", channel_url, tab.path());
+                if let Some(token) = continuation.take() {
+                    tab_url.push('?');
+                    tab_url.push_str(&serde_html_form::to_string([("continuation", token)])?);
+                }
+                let Some(data) = get_json(&tab_url, &self.client).await? else {
+                    break;
+                };
+                for item in data["videos"].as_array().into_iter().flatten() {
+                    match parse_video(item, tab.default_kind()) {
+                        Ok(video) => tab_videos.push(video),
+                        Err(err) => skip_item(err, &mut skipped),
+                    }
+                }
+                continuation = data["continuation"].as_str().map(str::to_string);
+                if continuation.is_none() || tab_videos.len() >= max_videos {
+                    break;
+                }
+                if pages == max_pages {
+                    tracing::debug!("stopping after {pages} pages of the {} tab", tab.path());
+                    break;
+                }
+                pages += 1;
+            }
+            tab_videos.truncate(max_videos);
+            for video in tab_videos {
+                if !videos.iter().any(|v| v.id == video.id) {
+                    videos.push(video);
+                }
+            }
+        }
+        tracing::debug!("extracted {} videos from '{}'", videos.len(), channel.title);
+        Ok(Extraction {
+            channel,
+            videos,
+            complete: true,
+            skipped,
+        })
+    }
+
+    async fn channel_id(&self, channel_path: &str) -> Result<String, Error> {
+        if let Some(channel_id) = channel_path.strip_prefix("channel/") {
+            return Ok(channel_id.to_string());
+        }
+        let not_found = || Error::ChannelNotFound(channel_path.to_string());
+        let query = serde_html_form::to_string([(
+            "url",
+            format!("https://www.youtube.com/{}", channel_path),
+        )])?;
+        let url = format!("{}/api/v1/resolveurl?{}", self.base_url, query);
+        let data = get_json(&url, &self.client).await?.ok_or_else(not_found)?;
+        let channel_id = data["ucid"].as_str().ok_or_else(not_found)?;
+        Ok(channel_id.to_string())
+    }
+
+    async fn playlist(&self, playlist_id: &str) -> Result<Extraction, Error> {
+        let url = format!("{}/api/v1/playlists/{}", self.base_url, playlist_id);
+        let data = get_json(&url, &self.client)
+            .await?
+            .ok_or_else(|| Error::PlaylistNotFound(playlist_id.to_string()))?;
+        let channel = Channel {
+            title: str_at(&data, "/title")?.to_string(),
+            url: format!("https://www.youtube.com/playlist?list={}", playlist_id),
+            id: playlist_id.to_string(),
+        };
+        let mut videos = Vec::new();
+        let mut skipped = 0;
+        for item in data["videos"].as_array().into_iter().flatten() {
+            match parse_video(item, VideoKind::Video) {
+                Ok(video) => videos.push(video),
+                Err(err) => skip_item(err, &mut skipped),
+            }
+        }
+        tracing::debug!("extracted {} videos from '{}'", videos.len(), channel.title);
+        // playlist videos are listed without publish dates and statistics
+        Ok(Extraction {
+            channel,
+            videos,
+            complete: false,
+            skipped,
+        })
+    }
+}
+
+impl Extractor for InvidiousExtractor {
+    fn name(&self) -> &'static str {
+        "invidious"
+    }
+
+    fn extract_channel<'a>(
+        &'a self,
+        channel_path: &'a str,
+        tabs: &'a [Tab],
+        max_videos: usize,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.channel(channel_path, tabs, max_videos))
+    }
+
+    fn extract_channel_id<'a>(
+        &'a self,
+        channel_path: &'a str,
+    ) -> BoxFut<'a, Result<String, Error>> {
+        Box::pin(self.channel_id(channel_path))
+    }
+
+    fn extract_playlist<'a>(
+        &'a self,
+        playlist_id: &'a str,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.playlist(playlist_id))
+    }
+}
+
+fn parse_video(item: &Value, default_kind: VideoKind) -> Result<VideoInfo, ScrapeError> {
+    let id = str_at(item, "/videoId")?.to_string();
+    let kind = if item["liveNow"].as_bool() == Some(true) {
+        VideoKind::Live
+    } else if item["isUpcoming"].as_bool() == Some(true) {
+        VideoKind::Upcoming
+    } else {
+        default_kind
+    };
+    let duration = if kind.has_duration() {
+        Duration::from_secs(item["lengthSeconds"].as_u64().unwrap_or(0))
+    } else {
+        Duration::ZERO
+    };
+    Ok(VideoInfo {
+        id,
+        duration,
+        kind,
+        title: item["title"].as_str().map(str::to_string),
+        views: item["viewCount"].as_u64(),
+        published: item["published"].as_i64().and_then(from_timestamp),
+        description: item["description"].as_str().map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub;
+
+    const ROUTES: &[(&str, &str)] = &[
+        (
+            "/api/v1/resolveurl?url=https%3A%2F%2Fwww.youtube.com%2F%40invidious",
+            "invidious_resolveurl.json",
+        ),
+        (
+            "/api/v1/channels/UCinvidious0000000000000?fields=author",
+            "invidious_channel.json",
+        ),
+        (
+            "/api/v1/channels/UCinvidious0000000000000/videos",
+            "invidious_videos.json",
+        ),
+        (
+            "/api/v1/channels/UCinvidious0000000000000/videos?continuation=page2",
+            "invidious_videos_page2.json",
+        ),
+        (
+            "/api/v1/channels/UCinvidious0000000000000/shorts",
+            "invidious_shorts.json",
+        ),
+    ];
+
+    /// The videos tab is empty and only links to itself
+    const ENDLESS_ROUTES: &[(&str, &str)] = &[
+        (
+            "/api/v1/channels/UCinvidious0000000000000?fields=author",
+            "invidious_channel.json",
+        ),
+        (
+            "/api/v1/channels/UCinvidious0000000000000/videos",
+            "invidious_videos_endless.json",
+        ),
+        (
+            "/api/v1/channels/UCinvidious0000000000000/videos?continuation=endless",
+            "invidious_videos_endless.json",
+        ),
+    ];
+
+    fn ids(extraction: &Extraction) -> Vec<&str> {
+        extraction.videos.iter().map(|v| v.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn channel() {
+        let url = stub::serve(ROUTES).await;
+        let extractor = InvidiousExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@invidious", &[Tab::Videos, Tab::Shorts], 30)
+            .await
+            .unwrap();
+
+        assert_eq!(extraction.channel.id, "UCinvidious0000000000000");
+        assert_eq!(extraction.channel.title, "Invidious Channel");
+        // the fourth video is on the continuation page, the item without an id is skipped
+        assert_eq!(
+            ids(&extraction),
+            [
+                "vidInvid001",
+                "vidInvid002",
+                "vidInvid003",
+                "vidInvid004",
+                "vidInvid005"
+            ]
+        );
+        assert_eq!(extraction.skipped, 1);
+        let kinds: Vec<VideoKind> = extraction.videos.iter().map(|v| v.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                VideoKind::Video,
+                VideoKind::Live,
+                VideoKind::Upcoming,
+                VideoKind::Video,
+                VideoKind::Short
+            ]
+        );
+
+        let video = &extraction.videos[0];
+        assert_eq!(video.duration, Duration::from_secs(754));
+        assert_eq!(video.views, Some(1234));
+        assert_eq!(video.published, from_timestamp(1_700_000_000));
+        assert_eq!(
+            video.description.as_deref(),
+            Some("First line\nSecond line")
+        );
+        // live and upcoming videos don't have a duration yet
+        assert_eq!(extraction.videos[2].duration, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn max_videos() {
+        let url = stub::serve(ROUTES).await;
+        let extractor = InvidiousExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("channel/UCinvidious0000000000000", &[Tab::Videos], 2)
+            .await
+            .unwrap();
+        assert_eq!(ids(&extraction), ["vidInvid001", "vidInvid002"]);
+    }
+
+    #[tokio::test]
+    async fn endless_continuation() {
+        let url = stub::serve(ENDLESS_ROUTES).await;
+        let extractor = InvidiousExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("channel/UCinvidious0000000000000", &[Tab::Videos], 30)
+            .await
+            .unwrap();
+        assert!(extraction.videos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn channel_id() {
+        let url = stub::serve(ROUTES).await;
+        let extractor = InvidiousExtractor::new(&url, Client::new());
+        assert_eq!(
+            extractor.extract_channel_id("@invidious").await.unwrap(),
+            "UCinvidious0000000000000"
+        );
+        assert!(matches!(
+            extractor.extract_channel_id("@missing").await,
+            Err(Error::ChannelNotFound(_))
+        ));
+    }
+}
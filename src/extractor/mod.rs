@@ -0,0 +1,1126 @@
+mod html;
+mod innertube;
+mod invidious;
+mod piped;
+
+use crate::{
+    cache::BoxFut,
+    error::{Error, ScrapeError},
+    feed::Channel,
+};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, TimeDelta, Utc};
+use html::HtmlExtractor;
+use innertube::InnerTubeExtractor;
+use invidious::InvidiousExtractor;
+use piped::PipedExtractor;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+
+/// Base URL of the YouTube website
+pub const YOUTUBE_URL: &str = "https://www.youtube.com";
+
+#[derive(Debug, Clone)]
+pub struct VideoInfo {
+    pub id: String,
+    /// Zero if the duration is not known, see [`VideoKind::has_duration`]
+    pub duration: Duration,
+    pub kind: VideoKind,
+    /// Title shown on the channel page
+    pub title: Option<String>,
+    /// View count shown on the channel page, rounded for large numbers (e.g. "1.2M views")
+    pub views: Option<u64>,
+    /// Publish date estimated from the relative time shown on the channel page (e.g. "3 weeks
+    /// ago")
+    pub published: Option<DateTime<FixedOffset>>,
+    /// Only provided by instance APIs, the channel page doesn't show descriptions
+    pub description: Option<String>,
+}
+
+/// Kind of content on a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoKind {
+    /// A regular uploaded video
+    Video,
+    /// A livestream or premiere that is currently live
+    Live,
+    /// A scheduled livestream or premiere
+    Upcoming,
+    /// A past livestream
+    Archived,
+    /// A YouTube short
+    Short,
+}
+
+impl VideoKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VideoKind::Video => "video",
+            VideoKind::Live => "live",
+            VideoKind::Upcoming => "upcoming",
+            VideoKind::Archived => "archived",
+            VideoKind::Short => "short",
+        }
+    }
+
+    /// Live and upcoming videos don't have a duration yet and the shorts tab doesn't show it
+    pub fn has_duration(&self) -> bool {
+        matches!(self, VideoKind::Video | VideoKind::Archived)
+    }
+
+    /// Prefix for the entry title, so that time-sensitive entries stand out in feed readers
+    pub fn title_prefix(&self) -> &'static str {
+        match self {
+            VideoKind::Live => "[Live] ",
+            VideoKind::Upcoming => "[Upcoming] ",
+            VideoKind::Short => "[Short] ",
+            VideoKind::Video | VideoKind::Archived => "",
+        }
+    }
+}
+
+/// A tab on a channel page to scrape videos from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Tab {
+    Videos,
+    Streams,
+    Shorts,
+}
+
+impl Tab {
+    pub fn path(&self) -> &'static str {
+        match self {
+            Tab::Videos => "videos",
+            Tab::Streams => "streams",
+            Tab::Shorts => "shorts",
+        }
+    }
+
+    /// Kind of the videos on the tab that are neither live nor upcoming
+    pub fn default_kind(&self) -> VideoKind {
+        match self {
+            Tab::Videos => VideoKind::Video,
+            Tab::Streams => VideoKind::Archived,
+            Tab::Shorts => VideoKind::Short,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    pub channel: Channel,
+    pub videos: Vec<VideoInfo>,
+    /// Whether the videos have all data for the feed (title, publish date, views and
+    /// description), so that the RSS feed doesn't have to be fetched
+    pub complete: bool,
+    /// Number of items that were skipped because they couldn't be parsed
+    pub skipped: usize,
+}
+
+/// A source of channel data and video information
+pub trait Extractor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Extracts channel data and the videos on the given tabs, up to `max_videos` per tab
+    ///
+    /// The channel path is any of the URL paths YouTube accepts for a channel, e.g. `@handle`,
+    /// `channel/UC...`, `c/Name` or `user/Name`.
+    fn extract_channel<'a>(
+        &'a self,
+        channel_path: &'a str,
+        tabs: &'a [Tab],
+        max_videos: usize,
+    ) -> BoxFut<'a, Result<Extraction, Error>>;
+
+    /// Resolves a channel path to the id of the channel
+    fn extract_channel_id<'a>(&'a self, channel_path: &'a str)
+        -> BoxFut<'a, Result<String, Error>>;
+
+    /// Extracts playlist data and the videos in the playlist
+    fn extract_playlist<'a>(
+        &'a self,
+        playlist_id: &'a str,
+    ) -> BoxFut<'a, Result<Extraction, Error>>;
+}
+
+/// Extractor backends that can be selected in the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Scrape the `ytInitialData` embedded in the YouTube website
+    Html,
+    /// Query the JSON API used by the YouTube website
+    Innertube,
+    /// Query the API of an Invidious instance
+    Invidious,
+    /// Query the API of a Piped instance
+    Piped,
+}
+
+/// Instances of alternative frontends that can be used instead of youtube.com
+#[derive(Debug, Clone, Default)]
+pub struct Instances {
+    /// Base URL of an Invidious instance
+    pub invidious: Option<String>,
+    /// Base URL of the API of a Piped instance
+    pub piped: Option<String>,
+}
+
+/// Creates an extractor that uses the given backend, and falls back to the other backends if it
+/// fails. The instance backends are only used if an instance is configured.
+///
+/// The YouTube backends use `youtube_url`, which is [`YOUTUBE_URL`] unless testing.
+pub fn with_fallback(
+    primary: Backend,
+    youtube_url: &str,
+    instances: &Instances,
+    client: Client,
+) -> Result<Arc<dyn Extractor>, String> {
+    let mut backends = vec![primary];
+    for backend in [
+        Backend::Html,
+        Backend::Innertube,
+        Backend::Invidious,
+        Backend::Piped,
+    ] {
+        if backend != primary {
+            backends.push(backend);
+        }
+    }
+    let mut extractors: Vec<Box<dyn Extractor>> = Vec::new();
+    for backend in backends {
+        match backend {
+            Backend::Html => {
+                extractors.push(Box::new(HtmlExtractor::new(youtube_url, client.clone())))
+            }
+            Backend::Innertube => extractors.push(Box::new(InnerTubeExtractor::new(
+                youtube_url,
+                client.clone(),
+            ))),
+            Backend::Invidious => match &instances.invidious {
+                Some(url) => {
+                    extractors.push(Box::new(InvidiousExtractor::new(url, client.clone())))
+                }
+                None if backend == primary => {
+                    return Err("the invidious extractor requires an Invidious instance".into())
+                }
+                None => {}
+            },
+            Backend::Piped => match &instances.piped {
+                Some(url) => extractors.push(Box::new(PipedExtractor::new(url, client.clone()))),
+                None if backend == primary => {
+                    return Err("the piped extractor requires a Piped instance".into())
+                }
+                None => {}
+            },
+        }
+    }
+    Ok(Arc::new(FallbackExtractor { extractors }))
+}
+
+/// Tries each extractor in order until one succeeds
+struct FallbackExtractor {
+    extractors: Vec<Box<dyn Extractor>>,
+}
+
+impl FallbackExtractor {
+    async fn try_each<'a, T>(
+        &'a self,
+        f: impl Fn(&'a dyn Extractor) -> BoxFut<'a, Result<T, Error>>,
+    ) -> Result<T, Error> {
+        let mut last_err = None;
+        for extractor in &self.extractors {
+            match f(extractor.as_ref()).await {
+                Ok(value) => return Ok(value),
+                // the other extractors won't find it either
+                Err(err @ (Error::ChannelNotFound(_) | Error::PlaylistNotFound(_))) => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    tracing::warn!("{} extractor failed: {err}", extractor.name());
+                    last_err = Some(err);
+                }
+            }
+        }
+        Err(last_err.unwrap_or(ScrapeError::Other("no extractors configured").into()))
+    }
+}
+
+impl Extractor for FallbackExtractor {
+    fn name(&self) -> &'static str {
+        "fallback"
+    }
+
+    fn extract_channel<'a>(
+        &'a self,
+        channel_path: &'a str,
+        tabs: &'a [Tab],
+        max_videos: usize,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.try_each(move |e| e.extract_channel(channel_path, tabs, max_videos)))
+    }
+
+    fn extract_channel_id<'a>(
+        &'a self,
+        channel_path: &'a str,
+    ) -> BoxFut<'a, Result<String, Error>> {
+        Box::pin(self.try_each(move |e| e.extract_channel_id(channel_path)))
+    }
+
+    fn extract_playlist<'a>(
+        &'a self,
+        playlist_id: &'a str,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.try_each(move |e| e.extract_playlist(playlist_id)))
+    }
+}
+
+/// Number of items YouTube shows on a page of a channel tab
+const ITEMS_PER_PAGE: usize = 30;
+
+/// Maximum number of continuation pages to fetch for a tab, one more than needed for the items
+/// that are skipped
+fn max_continuations(max_videos: usize) -> usize {
+    max_videos.div_ceil(ITEMS_PER_PAGE) + 1
+}
+
+/// Extracts channel data and video information from the data of one or more channel tabs
+///
+/// Up to `max_videos` videos are extracted from each tab, following continuations if the first
+/// page doesn't contain enough videos. The number of pages is limited, since pages can consist
+/// of items that aren't videos, and a page that fails to load ends the tab early.
+async fn extract_channel_pages(
+    pages: Vec<(Tab, Value)>,
+    max_videos: usize,
+    youtube_url: &str,
+    client: &Client,
+) -> Result<Extraction, Error> {
+    let mut channel = None;
+    let mut videos: Vec<VideoInfo> = Vec::new();
+    let mut skipped = 0;
+    for (tab, data) in pages {
+        if channel.is_none() {
+            let channel_id = str_at(&data, "/metadata/channelMetadataRenderer/externalId")?;
+            channel = Some(Channel {
+                title: str_at(&data, "/metadata/channelMetadataRenderer/title")?.to_string(),
+                url: format!("https://www.youtube.com/channel/{channel_id}"),
+                id: channel_id.to_string(),
+            });
+        }
+        let (mut tab_videos, mut continuation) = extract_tab_videos(&data, tab, &mut skipped)?;
+        let client_version = client_version(&data);
+        let max_pages = max_continuations(max_videos);
+        let mut pages = 0;
+        while let Some(token) = continuation.take() {
+            if tab_videos.len() >= max_videos {
+                break;
+            }
+            if pages == max_pages {
+                tracing::debug!("stopping after {pages} pages of the {} tab", tab.path());
+                break;
+            }
+            pages += 1;
+            tracing::debug!(
+                "fetching next page of the {} tab ({} videos so far)",
+                tab.path(),
+                tab_videos.len()
+            );
+            let page =
+                match innertube::fetch_continuation(&token, &client_version, youtube_url, client)
+                    .await
+                {
+                    Ok(page) => page,
+                    Err(err) => {
+                        tracing::warn!(
+                            "failed to fetch next page of the {} tab: {err}",
+                            tab.path()
+                        );
+                        break;
+                    }
+                };
+            let Some(items) = page["onResponseReceivedActions"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find_map(|action| {
+                    action["appendContinuationItemsAction"]["continuationItems"].as_array()
+                })
+            else {
+                break;
+            };
+            continuation = parse_grid_items(items, tab, &mut tab_videos, &mut skipped);
+        }
+        tab_videos.truncate(max_videos);
+        // Premieres can show up on both the videos and the streams tab
+        for video in tab_videos {
+            if !videos.iter().any(|v| v.id == video.id) {
+                videos.push(video);
+            }
+        }
+    }
+    let channel = channel.ok_or(ScrapeError::Other("no channel tabs to extract"))?;
+    tracing::debug!("extracted {} videos from '{}'", videos.len(), channel.title);
+    Ok(Extraction {
+        channel,
+        videos,
+        complete: false,
+        skipped,
+    })
+}
+
+/// Extracts the videos on the first page of a tab, and the continuation token for the next page
+fn extract_tab_videos(
+    data: &Value,
+    tab: Tab,
+    skipped: &mut usize,
+) -> Result<(Vec<VideoInfo>, Option<String>), Error> {
+    let tabs = data["contents"]["twoColumnBrowseResultsRenderer"]["tabs"].as_array();
+    // YouTube selects the home tab instead if the channel doesn't have the requested tab
+    let tab_suffix = format!("/{}", tab.path());
+    let Some(video_tab) = tabs
+        .into_iter()
+        .flatten()
+        .map(|t| &t["tabRenderer"])
+        .find(|t| {
+            t["selected"].as_bool() == Some(true)
+                && t["endpoint"]["commandMetadata"]["webCommandMetadata"]["url"]
+                    .as_str()
+                    .is_none_or(|url| url.ends_with(&tab_suffix))
+        })
+    else {
+        tracing::debug!("channel has no {} tab", tab.path());
+        return Ok((Vec::new(), None));
+    };
+    let items = array_at(video_tab, "/content/richGridRenderer/contents")?;
+    let mut videos = Vec::new();
+    let continuation = parse_grid_items(items, tab, &mut videos, skipped);
+    Ok((videos, continuation))
+}
+
+/// Parses the items of a rich grid into videos, returns the continuation token if there are more
+/// items. Items that can't be parsed are skipped.
+fn parse_grid_items(
+    items: &[Value],
+    tab: Tab,
+    videos: &mut Vec<VideoInfo>,
+    skipped: &mut usize,
+) -> Option<String> {
+    let mut continuation = None;
+    for item in items {
+        if let Some(token) = item["continuationItemRenderer"]["continuationEndpoint"]
+            ["continuationCommand"]["token"]
+            .as_str()
+        {
+            continuation = Some(token.to_string());
+            continue;
+        }
+        let Some(item_renderer) = item.get("richItemRenderer") else {
+            continue;
+        };
+        match parse_grid_item(&item_renderer["content"], tab) {
+            Ok(Some(video)) => videos.push(video),
+            Ok(None) => {}
+            Err(err) => skip_item(err, skipped),
+        }
+    }
+    continuation
+}
+
+/// Parses the content of a rich grid item, returns `None` if it is not a video (e.g. a playlist)
+fn parse_grid_item(content: &Value, tab: Tab) -> Result<Option<VideoInfo>, ScrapeError> {
+    let (id, kind, length_text, title, metadata_texts) = if let Some(lockup) =
+        content.get("lockupViewModel")
+    {
+        if lockup.get("contentType").and_then(Value::as_str) != Some("LOCKUP_CONTENT_TYPE_VIDEO") {
+            return Ok(None);
+        }
+        let id = str_at(lockup, "/contentId")?;
+        let metadata = &lockup["metadata"]["lockupMetadataViewModel"];
+        let title = metadata["title"]["content"].as_str();
+        let metadata_texts: Vec<&str> = metadata["metadata"]["contentMetadataViewModel"]
+            ["metadataRows"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|row| row["metadataParts"].as_array().into_iter().flatten())
+            .filter_map(|part| part["text"]["content"].as_str())
+            .collect();
+        let badges = lockup_badges(lockup);
+        if badges.iter().any(|b| is_live_badge(b)) {
+            (id, VideoKind::Live, None, title, metadata_texts)
+        } else if let Some(length) = find_duration_badge(lockup) {
+            (id, tab.default_kind(), Some(length), title, metadata_texts)
+        } else if badges.iter().any(|b| is_upcoming_badge(b)) {
+            (id, VideoKind::Upcoming, None, title, metadata_texts)
+        } else {
+            let badge_texts: Vec<&str> = badges.iter().filter_map(|b| b["text"].as_str()).collect();
+            return Err(ScrapeError::unexpected(
+                "/contentImage/thumbnailViewModel/overlays",
+                format!("{id} has no duration badge, only {badge_texts:?}"),
+            ));
+        }
+    } else if let Some(video_renderer) = content.get("videoRenderer") {
+        let id = str_at(video_renderer, "/videoId")?;
+        let title = video_renderer["title"]["runs"][0]["text"].as_str();
+        let metadata_texts: Vec<&str> = [
+            video_renderer["viewCountText"]["simpleText"].as_str(),
+            video_renderer["publishedTimeText"]["simpleText"].as_str(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if video_renderer.get("upcomingEventData").is_some() {
+            (id, VideoKind::Upcoming, None, title, metadata_texts)
+        } else if time_status_style(video_renderer) == Some("LIVE") {
+            (id, VideoKind::Live, None, title, metadata_texts)
+        } else {
+            let length_text = str_at(video_renderer, "/lengthText/simpleText")?;
+            let length = ("/lengthText/simpleText".to_string(), length_text);
+            (id, tab.default_kind(), Some(length), title, metadata_texts)
+        }
+    } else if let Some(short) = content.get("shortsLockupViewModel") {
+        let id = str_at(short, "/onTap/innertubeCommand/reelWatchEndpoint/videoId")?;
+        let metadata = &short["overlayMetadata"];
+        let title = metadata["primaryText"]["content"].as_str();
+        let metadata_texts: Vec<&str> = metadata["secondaryText"]["content"]
+            .as_str()
+            .into_iter()
+            .collect();
+        (id, VideoKind::Short, None, title, metadata_texts)
+    } else if let Some(reel_renderer) = content.get("reelItemRenderer") {
+        let id = str_at(reel_renderer, "/videoId")?;
+        let title = reel_renderer["headline"]["simpleText"].as_str();
+        let metadata_texts: Vec<&str> = reel_renderer["viewCountText"]["simpleText"]
+            .as_str()
+            .into_iter()
+            .collect();
+        (id, VideoKind::Short, None, title, metadata_texts)
+    } else {
+        return Ok(None);
+    };
+    let duration = match length_text {
+        Some((path, length_text)) => parse_length_text(length_text)
+            .ok_or_else(|| ScrapeError::unexpected(path, length_text))?,
+        None => Duration::ZERO,
+    };
+    let (views, published) = parse_metadata_texts(&metadata_texts);
+    Ok(Some(VideoInfo {
+        id: id.to_string(),
+        duration,
+        kind,
+        title: title.map(str::to_string),
+        views,
+        published,
+        description: None,
+    }))
+}
+
+/// Version of the web client that rendered the page, the InnerTube API expects a recent one
+fn client_version(data: &Value) -> String {
+    data["responseContext"]["serviceTrackingParams"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|service| service["params"].as_array().into_iter().flatten())
+        .find(|param| param["key"].as_str() == Some("cver"))
+        .and_then(|param| param["value"].as_str())
+        .unwrap_or(innertube::CLIENT_VERSION)
+        .to_string()
+}
+
+/// Extracts playlist data and video information from the data of a playlist page
+fn extract_playlist_page(playlist_id: &str, data: &Value) -> Result<Extraction, Error> {
+    // YouTube serves an error page with an alert instead of a 404 for unknown playlists
+    if has_error_alert(data) {
+        return Err(Error::PlaylistNotFound(playlist_id.to_string()));
+    }
+    let title = str_at(data, "/metadata/playlistMetadataRenderer/title")?;
+    let channel = Channel {
+        title: title.to_string(),
+        url: format!("https://www.youtube.com/playlist?list={}", playlist_id),
+        id: playlist_id.to_string(),
+    };
+    let items = array_at(
+        data,
+        "/contents/twoColumnBrowseResultsRenderer/tabs/0/tabRenderer/content/sectionListRenderer\
+         /contents/0/itemSectionRenderer/contents/0/playlistVideoListRenderer/contents",
+    )?;
+    let mut videos = Vec::new();
+    let mut skipped = 0;
+    for item in items {
+        let Some(video_renderer) = item.get("playlistVideoRenderer") else {
+            continue;
+        };
+        match parse_playlist_item(video_renderer) {
+            Ok(Some(video)) => videos.push(video),
+            Ok(None) => {}
+            Err(err) => skip_item(err, &mut skipped),
+        }
+    }
+    tracing::debug!("extracted {} videos from '{}'", videos.len(), channel.title);
+    Ok(Extraction {
+        channel,
+        videos,
+        complete: false,
+        skipped,
+    })
+}
+
+/// Parses a video in a playlist, returns `None` for deleted and private videos
+fn parse_playlist_item(video_renderer: &Value) -> Result<Option<VideoInfo>, ScrapeError> {
+    let title = video_renderer["title"]["runs"][0]["text"].as_str();
+    // e.g. "1.2M views", " • ", "3 years ago"
+    let metadata_texts: Vec<&str> = video_renderer["videoInfo"]["runs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|run| run["text"].as_str())
+        .collect();
+    let (views, published) = parse_metadata_texts(&metadata_texts);
+    let (kind, duration) = match time_status_style(video_renderer) {
+        // Shorts in playlists are marked with a time status overlay instead of a length
+        Some("SHORTS") => (VideoKind::Short, Duration::ZERO),
+        Some("LIVE") => (VideoKind::Live, Duration::ZERO),
+        Some("UPCOMING") => (VideoKind::Upcoming, Duration::ZERO),
+        _ => {
+            // Deleted and private videos are listed without a length
+            let Some(length_text) = video_renderer["lengthText"]["simpleText"].as_str() else {
+                return Ok(None);
+            };
+            let duration = parse_length_text(length_text)
+                .ok_or_else(|| ScrapeError::unexpected("/lengthText/simpleText", length_text))?;
+            (VideoKind::Video, duration)
+        }
+    };
+    Ok(Some(VideoInfo {
+        id: str_at(video_renderer, "/videoId")?.to_string(),
+        duration,
+        kind,
+        title: title.map(str::to_string),
+        views,
+        published,
+        description: None,
+    }))
+}
+
+/// Whether YouTube shows an error alert instead of the content, e.g. "This playlist does not
+/// exist." or "This account has been terminated"
+fn has_error_alert(data: &Value) -> bool {
+    data["alerts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .any(|alert| alert["alertRenderer"]["type"].as_str() == Some("ERROR"))
+}
+
+/// Fetches JSON from an API, returns `None` if it responds with a 404
+async fn get_json(url: &str, client: &Client) -> Result<Option<Value>, Error> {
+    tracing::debug!("querying '{}'", url);
+    let response = client.get(url).send().await?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let text = response.error_for_status()?.text().await?;
+    Ok(Some(serde_json::from_str(&text)?))
+}
+
+/// Converts a timestamp in seconds since the Unix epoch to a date
+fn from_timestamp(secs: i64) -> Option<DateTime<FixedOffset>> {
+    DateTime::from_timestamp(secs, 0).map(|dt| dt.fixed_offset())
+}
+
+/// Parses a duration in the `h:mm:ss` or `m:ss` format
+fn parse_length_text(length_text: &str) -> Option<Duration> {
+    let parts = length_text
+        .split(':')
+        .map(|part| part.parse::<u64>().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    match parts[..] {
+        [hours, minutes, seconds] => {
+            Some(Duration::from_secs(hours * 3600 + minutes * 60 + seconds))
+        }
+        [minutes, seconds] => Some(Duration::from_secs(minutes * 60 + seconds)),
+        _ => None,
+    }
+}
+
+/// Gets the string at a JSON pointer, e.g. `/metadata/channelMetadataRenderer/title`
+fn str_at<'a>(value: &'a Value, pointer: &str) -> Result<&'a str, ScrapeError> {
+    let found = value
+        .pointer(pointer)
+        .ok_or_else(|| ScrapeError::missing(pointer))?;
+    found
+        .as_str()
+        .ok_or_else(|| ScrapeError::unexpected(pointer, found))
+}
+
+/// Gets the array at a JSON pointer
+fn array_at<'a>(value: &'a Value, pointer: &str) -> Result<&'a Vec<Value>, ScrapeError> {
+    let found = value
+        .pointer(pointer)
+        .ok_or_else(|| ScrapeError::missing(pointer))?;
+    found
+        .as_array()
+        .ok_or_else(|| ScrapeError::unexpected(pointer, found))
+}
+
+/// Logs why an item is skipped and counts it, so that one odd video doesn't fail the whole feed
+fn skip_item(err: ScrapeError, skipped: &mut usize) {
+    tracing::warn!("skipping item: {err}");
+    *skipped += 1;
+}
+
+/// Finds the view count and the estimated publish date in metadata texts such as "1.2M views"
+/// and "Streamed 3 weeks ago"
+fn parse_metadata_texts(texts: &[&str]) -> (Option<u64>, Option<DateTime<FixedOffset>>) {
+    let views = texts.iter().find_map(|text| parse_view_count(text));
+    let published = texts.iter().find_map(|text| parse_relative_time(text));
+    (views, published)
+}
+
+/// Parses view counts such as "1,234 views", "1.2M views" or "No views"
+fn parse_view_count(text: &str) -> Option<u64> {
+    let count = text
+        .strip_suffix(" views")
+        .or_else(|| text.strip_suffix(" view"))?
+        .trim();
+    if count == "No" {
+        return Some(0);
+    }
+    let (number, multiplier) = match count.chars().last()? {
+        'K' => (&count[..count.len() - 1], 1_000.0),
+        'M' => (&count[..count.len() - 1], 1_000_000.0),
+        'B' => (&count[..count.len() - 1], 1_000_000_000.0),
+        _ => (count, 1.0),
+    };
+    let number: f64 = number.replace(',', "").parse().ok()?;
+    Some((number * multiplier) as u64)
+}
+
+/// Estimates a date from relative times such as "3 weeks ago" or "Streamed 1 day ago"
+///
+/// The estimate is rounded down to the start of the day, or of the month or year for older
+/// videos, so that it doesn't change every time the channel page is scraped.
+fn parse_relative_time(text: &str) -> Option<DateTime<FixedOffset>> {
+    let mut words = text.strip_suffix(" ago")?.split_whitespace().rev();
+    let unit = words.next()?.trim_end_matches('s');
+    let amount: i64 = words.next()?.parse().ok()?;
+    let delta = match unit {
+        "second" => TimeDelta::try_seconds(amount),
+        "minute" => TimeDelta::try_minutes(amount),
+        "hour" => TimeDelta::try_hours(amount),
+        "day" => TimeDelta::try_days(amount),
+        "week" => TimeDelta::try_weeks(amount),
+        "month" => TimeDelta::try_days(amount.checked_mul(30)?),
+        "year" => TimeDelta::try_days(amount.checked_mul(365)?),
+        _ => return None,
+    }?;
+    let date = Utc::now().checked_sub_signed(delta)?.date_naive();
+    let date = match unit {
+        "month" => date.with_day(1)?,
+        "year" => date.with_ordinal(1)?,
+        _ => date,
+    };
+    Some(date.and_time(NaiveTime::MIN).and_utc().fixed_offset())
+}
+
+/// Finds the duration badge of a lockup, returns the JSON pointer to its text and the text
+fn find_duration_badge(lockup: &Value) -> Option<(String, &str)> {
+    let overlays = lockup["contentImage"]["thumbnailViewModel"]["overlays"].as_array()?;
+    overlays.iter().enumerate().find_map(|(i, overlay)| {
+        let badges = overlay["thumbnailBottomOverlayViewModel"]["badges"].as_array()?;
+        badges.iter().enumerate().find_map(|(j, badge)| {
+            let text = badge["thumbnailBadgeViewModel"]["text"].as_str()?;
+            let pointer = format!(
+                "/contentImage/thumbnailViewModel/overlays/{i}/thumbnailBottomOverlayViewModel\
+                 /badges/{j}/thumbnailBadgeViewModel/text"
+            );
+            text.contains(':').then_some((pointer, text))
+        })
+    })
+}
+
+/// Returns the badges shown on the thumbnail of a lockup
+fn lockup_badges(lockup: &Value) -> Vec<&Value> {
+    let Some(overlays) = lockup["contentImage"]["thumbnailViewModel"]["overlays"].as_array() else {
+        return Vec::new();
+    };
+    let mut result = Vec::new();
+    for overlay in overlays {
+        let badges = overlay["thumbnailBottomOverlayViewModel"]["badges"].as_array();
+        let Some(badges) = badges else { continue };
+        for badge in badges {
+            result.push(&badge["thumbnailBadgeViewModel"]);
+        }
+    }
+    result
+}
+
+fn is_live_badge(badge: &Value) -> bool {
+    badge["badgeStyle"].as_str() == Some("THUMBNAIL_OVERLAY_BADGE_STYLE_LIVE")
+        || badge["text"].as_str() == Some("LIVE")
+}
+
+fn is_upcoming_badge(badge: &Value) -> bool {
+    matches!(badge["text"].as_str(), Some("UPCOMING" | "PREMIERE"))
+}
+
+/// Style of the time status overlay of a (legacy) video renderer, e.g. `DEFAULT`, `LIVE`,
+/// `UPCOMING` or `SHORTS`
+fn time_status_style(renderer: &Value) -> Option<&str> {
+    renderer["thumbnailOverlays"]
+        .as_array()?
+        .iter()
+        .find_map(|overlay| overlay["thumbnailOverlayTimeStatusRenderer"]["style"].as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub;
+    use serde_json::json;
+
+    const LOCKUP_ROUTES: &[(&str, &str)] = &[
+        ("/@lockup", "channel_lockup.html"),
+        ("/@lockup/videos", "channel_lockup.html"),
+        ("/@lockup/shorts", "channel_lockup.html"),
+        (
+            "/youtubei/v1/browse?prettyPrint=false",
+            "channel_lockup_continuation.json",
+        ),
+    ];
+
+    const RENDERER_ROUTES: &[(&str, &str)] = &[("/@renderer/videos", "channel_renderer.html")];
+
+    /// Without the continuation page
+    const LOCKUP_FIRST_PAGE_ROUTES: &[(&str, &str)] = &[("/@lockup/videos", "channel_lockup.html")];
+
+    /// The continuation page only links to itself
+    const LOCKUP_ENDLESS_ROUTES: &[(&str, &str)] = &[
+        ("/@lockup/videos", "channel_lockup.html"),
+        (
+            "/youtubei/v1/browse?prettyPrint=false",
+            "channel_lockup_continuation_endless.json",
+        ),
+    ];
+
+    const PLAYLIST_ROUTES: &[(&str, &str)] = &[("/playlist?list=PLfixture", "playlist.html")];
+
+    /// A consent page without data instead of the channel
+    const CONSENT_ROUTES: &[(&str, &str)] = &[
+        ("/@lockup", "consent.html"),
+        ("/@lockup/videos", "consent.html"),
+    ];
+
+    fn ids(extraction: &Extraction) -> Vec<&str> {
+        extraction.videos.iter().map(|v| v.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn lockup_layout() {
+        let url = stub::serve(LOCKUP_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@lockup", &[Tab::Videos], 30)
+            .await
+            .unwrap();
+
+        assert_eq!(extraction.channel.id, "UClockupLayout00000000000");
+        assert_eq!(extraction.channel.title, "Lockup Layout");
+        // the last video is on the continuation page
+        assert_eq!(
+            ids(&extraction),
+            ["vidLockup01", "vidLockup02", "vidLockup03", "vidLockup04"]
+        );
+        let kinds: Vec<VideoKind> = extraction.videos.iter().map(|v| v.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                VideoKind::Video,
+                VideoKind::Live,
+                VideoKind::Upcoming,
+                VideoKind::Video
+            ]
+        );
+
+        let video = &extraction.videos[0];
+        assert_eq!(video.duration, Duration::from_secs(12 * 60 + 34));
+        assert_eq!(video.title.as_deref(), Some("Regular video"));
+        assert_eq!(video.views, Some(1_200_000));
+        assert!(video.published.is_some());
+
+        let live = &extraction.videos[1];
+        assert_eq!(live.duration, Duration::ZERO);
+        assert_eq!(live.views, None);
+        assert_eq!(live.published, None);
+
+        let old = &extraction.videos[3];
+        assert_eq!(old.duration, Duration::from_secs(3600 + 2 * 60 + 3));
+        assert_eq!(old.views, Some(1234));
+    }
+
+    #[tokio::test]
+    async fn lockup_layout_max_videos() {
+        let url = stub::serve(LOCKUP_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@lockup", &[Tab::Videos], 2)
+            .await
+            .unwrap();
+        assert_eq!(ids(&extraction), ["vidLockup01", "vidLockup02"]);
+    }
+
+    #[tokio::test]
+    async fn failed_continuation() {
+        let url = stub::serve(LOCKUP_FIRST_PAGE_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@lockup", &[Tab::Videos], 30)
+            .await
+            .unwrap();
+        // the videos of the first page are kept
+        assert_eq!(
+            ids(&extraction),
+            ["vidLockup01", "vidLockup02", "vidLockup03"]
+        );
+    }
+
+    #[tokio::test]
+    async fn endless_continuation() {
+        let url = stub::serve(LOCKUP_ENDLESS_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@lockup", &[Tab::Videos], 30)
+            .await
+            .unwrap();
+        assert_eq!(extraction.videos.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn renderer_layout() {
+        let url = stub::serve(RENDERER_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@renderer", &[Tab::Videos], 30)
+            .await
+            .unwrap();
+
+        assert_eq!(extraction.channel.id, "UCrendererLayout000000000");
+        assert_eq!(
+            ids(&extraction),
+            ["vidRender01", "vidRender02", "vidRender03"]
+        );
+        let video = &extraction.videos[0];
+        assert_eq!(video.kind, VideoKind::Video);
+        assert_eq!(video.duration, Duration::from_secs(4 * 60 + 5));
+        assert_eq!(video.views, Some(0));
+        assert!(video.published.is_some());
+        assert_eq!(extraction.videos[1].kind, VideoKind::Upcoming);
+        assert_eq!(extraction.videos[2].kind, VideoKind::Live);
+    }
+
+    #[tokio::test]
+    async fn missing_tab() {
+        // YouTube shows the videos tab if the channel doesn't have shorts
+        let url = stub::serve(LOCKUP_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@lockup", &[Tab::Shorts], 30)
+            .await
+            .unwrap();
+        assert!(extraction.videos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn channel_id() {
+        let url = stub::serve(LOCKUP_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        assert_eq!(
+            extractor.extract_channel_id("@lockup").await.unwrap(),
+            "UClockupLayout00000000000"
+        );
+        assert!(matches!(
+            extractor.extract_channel_id("@missing").await,
+            Err(Error::ChannelNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn playlist() {
+        let url = stub::serve(PLAYLIST_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        let extraction = extractor.extract_playlist("PLfixture").await.unwrap();
+
+        assert_eq!(extraction.channel.id, "PLfixture");
+        assert_eq!(extraction.channel.title, "Fixture Playlist");
+        // deleted and private videos are left out, the video with a bad length is skipped
+        assert_eq!(
+            ids(&extraction),
+            [
+                "vidPlay0008",
+                "vidPlay0001",
+                "vidPlay0004",
+                "vidPlay0005",
+                "vidPlay0006"
+            ]
+        );
+        assert_eq!(extraction.skipped, 1);
+        let kinds: Vec<VideoKind> = extraction.videos.iter().map(|v| v.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                VideoKind::Video,
+                VideoKind::Video,
+                VideoKind::Short,
+                VideoKind::Live,
+                VideoKind::Upcoming
+            ]
+        );
+
+        let video = &extraction.videos[0];
+        assert_eq!(video.duration, Duration::from_secs(4 * 60 + 5));
+        assert_eq!(video.title.as_deref(), Some("Regular video"));
+        assert_eq!(video.views, Some(10));
+        assert!(video.published.is_some());
+        let short = &extraction.videos[2];
+        assert_eq!(short.duration, Duration::ZERO);
+        assert_eq!(short.views, Some(98_000));
+        assert_eq!(extraction.videos[4].published, None);
+
+        assert!(matches!(
+            extractor.extract_playlist("PLmissing").await,
+            Err(Error::PlaylistNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn page_without_data() {
+        let url = stub::serve(CONSENT_ROUTES).await;
+        let extractor = HtmlExtractor::new(&url, Client::new());
+        // not a missing channel, so that it is retried and the other extractors are tried
+        let result = extractor
+            .extract_channel("@lockup", &[Tab::Videos], 30)
+            .await;
+        assert!(matches!(result, Err(Error::Scrape(_))));
+        let result = extractor.extract_channel_id("@lockup").await;
+        assert!(matches!(result, Err(Error::Scrape(_))));
+    }
+
+    #[test]
+    fn playlist_alert() {
+        let alert = json!({ "alerts": [{ "alertRenderer": {
+            "type": "ERROR",
+            "text": { "runs": [{ "text": "The playlist does not exist." }] },
+        } }] });
+        assert!(matches!(
+            extract_playlist_page("PL123", &alert),
+            Err(Error::PlaylistNotFound(_))
+        ));
+        assert!(matches!(
+            extract_playlist_page("PL123", &json!({})),
+            Err(Error::Scrape(_))
+        ));
+    }
+
+    #[test]
+    fn duration_badge() {
+        let lockup = |badges: Value| {
+            json!({
+                "contentImage": { "thumbnailViewModel": { "overlays": [
+                    { "thumbnailHoverOverlayToggleActionsViewModel": {} },
+                    { "thumbnailBottomOverlayViewModel": { "badges": badges } },
+                ] } }
+            })
+        };
+        let badge = |text: &str| json!({ "thumbnailBadgeViewModel": { "text": text } });
+
+        assert_eq!(
+            find_duration_badge(&lockup(json!([badge("12:34")]))).map(|(_, text)| text),
+            Some("12:34")
+        );
+        // the second badge of the second overlay
+        let two_badges = lockup(json!([badge("4K"), badge("1:02:03")]));
+        let (pointer, text) = find_duration_badge(&two_badges).unwrap();
+        assert_eq!(text, "1:02:03");
+        assert_eq!(two_badges.pointer(&pointer), Some(&json!("1:02:03")));
+        assert_eq!(
+            pointer,
+            "/contentImage/thumbnailViewModel/overlays/1/thumbnailBottomOverlayViewModel/badges/1\
+             /thumbnailBadgeViewModel/text"
+        );
+        assert_eq!(find_duration_badge(&lockup(json!([badge("LIVE")]))), None);
+        assert_eq!(find_duration_badge(&json!({})), None);
+    }
+
+    #[test]
+    fn skip_bad_items() {
+        let renderer = |id: &str, length: &str| {
+            json!({ "richItemRenderer": { "content": { "videoRenderer": {
+                "videoId": id,
+                "lengthText": { "simpleText": length },
+            } } } })
+        };
+        let items = [
+            renderer("vidRender01", "4:05"),
+            renderer("vidRender02", "SHORTS"),
+            json!({ "richItemRenderer": { "content": { "videoRenderer": { "videoId": 3 } } } }),
+        ];
+        let mut videos = Vec::new();
+        let mut skipped = 0;
+        parse_grid_items(&items, Tab::Videos, &mut videos, &mut skipped);
+        assert_eq!(videos.len(), 1);
+        assert_eq!(skipped, 2);
+
+        let err = parse_grid_item(&items[1]["richItemRenderer"]["content"], Tab::Videos);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "unexpected value at '/lengthText/simpleText': SHORTS"
+        );
+        let err = parse_grid_item(&items[2]["richItemRenderer"]["content"], Tab::Videos);
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "unexpected value at '/videoId': 3"
+        );
+    }
+
+    #[test]
+    fn length_text() {
+        assert_eq!(parse_length_text("0:59"), Some(Duration::from_secs(59)));
+        assert_eq!(
+            parse_length_text("10:00:01"),
+            Some(Duration::from_secs(36001))
+        );
+        assert_eq!(parse_length_text("59"), None);
+        assert_eq!(parse_length_text("LIVE"), None);
+    }
+
+    #[test]
+    fn view_count() {
+        assert_eq!(parse_view_count("No views"), Some(0));
+        assert_eq!(parse_view_count("1 view"), Some(1));
+        assert_eq!(parse_view_count("1,234 views"), Some(1234));
+        assert_eq!(parse_view_count("3.4K views"), Some(3400));
+        assert_eq!(parse_view_count("1.2M views"), Some(1_200_000));
+        assert_eq!(parse_view_count("42 watching"), None);
+    }
+
+    #[test]
+    fn relative_time() {
+        let published = parse_relative_time("Streamed 3 weeks ago").unwrap();
+        let date = (Utc::now() - TimeDelta::weeks(3)).date_naive();
+        assert_eq!(published.date_naive(), date);
+        assert_eq!(published.time(), NaiveTime::MIN);
+        assert_eq!(
+            parse_relative_time("5 hours ago"),
+            parse_relative_time("5 hours ago")
+        );
+        assert_eq!(parse_relative_time("2 months ago").unwrap().day(), 1);
+        assert_eq!(parse_relative_time("1 year ago").unwrap().ordinal(), 1);
+        assert!(parse_relative_time("9223372036854775807 years ago").is_none());
+        assert!(parse_relative_time("9223372036854775807 seconds ago").is_none());
+        assert!(parse_relative_time("Scheduled for 10/20/26").is_none());
+    }
+}
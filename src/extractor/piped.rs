@@ -0,0 +1,406 @@
+use super::{
+    get_json, max_continuations, skip_item, str_at, Extraction, Extractor, Tab, VideoInfo,
+    VideoKind,
+};
+use crate::{
+    cache::BoxFut,
+    error::{Error, ScrapeError},
+    feed::Channel,
+};
+use chrono::{DateTime, Utc};
+use reqwest::Client;
+use serde_json::Value;
+use std::time::Duration;
+
+/// Extracts data from the API of a Piped instance
+pub struct PipedExtractor {
+    base_url: String,
+    client: Client,
+}
+
+impl PipedExtractor {
+    pub fn new(base_url: &str, client: Client) -> Self {
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+        }
+    }
+
+    /// Fetches the channel info, which includes the first page of videos and the other tabs
+    async fn channel_info(&self, channel_path: &str) -> Result<Value, Error> {
+        // Piped uses the same paths as YouTube, except for handles
+        let api_path = match channel_path.strip_prefix('@') {
+            Some(handle) => format!("@/{handle}"),
+            None => channel_path.to_string(),
+        };
+        let url = format!("{}/{}", self.base_url, api_path);
+        get_json(&url, &self.client)
+            .await?
+            .ok_or_else(|| Error::ChannelNotFound(channel_path.to_string()))
+    }
+
+    async fn channel(
+        &self,
+        channel_path: &str,
+        tabs: &[Tab],
+        max_videos: usize,
+    ) -> Result<Extraction, Error> {
+        let info = self.channel_info(channel_path).await?;
+        let channel_id = str_at(&info, "/id")?.to_string();
+        let channel = Channel {
+            title: str_at(&info, "/name")?.to_string(),
+            url: format!("https://www.youtube.com/channel/{channel_id}"),
+            id: channel_id,
+        };
+        let mut videos: Vec<VideoInfo> = Vec::new();
+        let mut skipped = 0;
+        for tab in tabs {
+            let tab_videos = match tab {
+                Tab::Videos => self.videos_tab(&info, max_videos, &mut skipped).await?,
+                Tab::Streams => {
+                    self.other_tab(&info, "livestreams", *tab, max_videos, &mut skipped)
+                        .await?
+                }
+                Tab::Shorts => {
+                    self.other_tab(&info, "shorts", *tab, max_videos, &mut skipped)
+                        .await?
+                }
+            };
+            for video in tab_videos {
+                if !videos.iter().any(|v| v.id == video.id) {
+                    videos.push(video);
+                }
+            }
+        }
+        tracing::debug!("extracted {} videos from '{}'", videos.len(), channel.title);
+        Ok(Extraction {
+            channel,
+            videos,
+            complete: true,
+            skipped,
+        })
+    }
+
+    async fn videos_tab(
+        &self,
+        info: &Value,
+        max_videos: usize,
+        skipped: &mut usize,
+    ) -> Result<Vec<VideoInfo>, Error> {
+        let mut videos = parse_streams(&info["relatedStreams"], Tab::Videos, skipped);
+        let mut nextpage = info["nextpage"].as_str().map(str::to_string);
+        let max_pages = max_continuations(max_videos);
+        let mut pages = 0;
+        while let Some(token) = nextpage.take() {
+            if videos.len() >= max_videos {
+                break;
+            }
+            if pages == max_pages {
+                tracing::debug!("stopping after {pages} pages of the videos tab");
+                break;
+            }
+            pages += 1;
+            let query = serde_html_form::to_string([("nextpage", token)])?;
+            let url = format!(
+                "{}/nextpage/channel/{}?{}",
+                self.base_url,
+                info["id"].as_str().unwrap_or_default(),
+                query
+            );
+            let Some(page) = get_json(&url, &self.client).await? else {
+                break;
+            };
+            videos.extend(parse_streams(&page["relatedStreams"], Tab::Videos, skipped));
+            nextpage = page["nextpage"].as_str().map(str::to_string);
+        }
+        videos.truncate(max_videos);
+        Ok(videos)
+    }
+
+    async fn other_tab(
+        &self,
+        info: &Value,
+        name: &str,
+        tab: Tab,
+        max_videos: usize,
+        skipped: &mut usize,
+    ) -> Result<Vec<VideoInfo>, Error> {
+        let Some(data) = info["tabs"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|t| t["name"].as_str() == Some(name))
+            .and_then(|t| t["data"].as_str())
+        else {
+            tracing::debug!("channel has no {} tab", tab.path());
+            return Ok(Vec::new());
+        };
+        let mut videos = Vec::new();
+        let mut nextpage = None;
+        let max_pages = max_continuations(max_videos);
+        let mut pages = 0;
+        loop {
+            let mut params = vec![("data", data.to_string())];
+            if let Some(token) = nextpage.take() {
+                params.push(("nextpage", token));
+            }
+            let query = serde_html_form::to_string(&params)?;
+            let url = format!("{}/channels/tabs?{}", self.base_url, query);
+            let Some(page) = get_json(&url, &self.client).await? else {
+                break;
+            };
+            videos.extend(parse_streams(&page["content"], tab, skipped));
+            nextpage = page["nextpage"].as_str().map(str::to_string);
+            if nextpage.is_none() || videos.len() >= max_videos {
+                break;
+            }
+            if pages == max_pages {
+                tracing::debug!("stopping after {pages} pages of the {} tab", tab.path());
+                break;
+            }
+            pages += 1;
+        }
+        videos.truncate(max_videos);
+        Ok(videos)
+    }
+
+    async fn channel_id(&self, channel_path: &str) -> Result<String, Error> {
+        if let Some(channel_id) = channel_path.strip_prefix("channel/") {
+            return Ok(channel_id.to_string());
+        }
+        let info = self.channel_info(channel_path).await?;
+        let channel_id = str_at(&info, "/id")?;
+        Ok(channel_id.to_string())
+    }
+
+    async fn playlist(&self, playlist_id: &str) -> Result<Extraction, Error> {
+        let url = format!("{}/playlists/{}", self.base_url, playlist_id);
+        let data = get_json(&url, &self.client)
+            .await?
+            .ok_or_else(|| Error::PlaylistNotFound(playlist_id.to_string()))?;
+        let channel = Channel {
+            title: str_at(&data, "/name")?.to_string(),
+            url: format!("https://www.youtube.com/playlist?list={}", playlist_id),
+            id: playlist_id.to_string(),
+        };
+        let mut skipped = 0;
+        let videos = parse_streams(&data["relatedStreams"], Tab::Videos, &mut skipped);
+        tracing::debug!("extracted {} videos from '{}'", videos.len(), channel.title);
+        // playlist videos are listed without publish dates
+        Ok(Extraction {
+            channel,
+            videos,
+            complete: false,
+            skipped,
+        })
+    }
+}
+
+impl Extractor for PipedExtractor {
+    fn name(&self) -> &'static str {
+        "piped"
+    }
+
+    fn extract_channel<'a>(
+        &'a self,
+        channel_path: &'a str,
+        tabs: &'a [Tab],
+        max_videos: usize,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.channel(channel_path, tabs, max_videos))
+    }
+
+    fn extract_channel_id<'a>(
+        &'a self,
+        channel_path: &'a str,
+    ) -> BoxFut<'a, Result<String, Error>> {
+        Box::pin(self.channel_id(channel_path))
+    }
+
+    fn extract_playlist<'a>(
+        &'a self,
+        playlist_id: &'a str,
+    ) -> BoxFut<'a, Result<Extraction, Error>> {
+        Box::pin(self.playlist(playlist_id))
+    }
+}
+
+/// Parses a list of Piped stream items, skipping channels, playlists and items that can't be
+/// parsed
+fn parse_streams(items: &Value, tab: Tab, skipped: &mut usize) -> Vec<VideoInfo> {
+    let mut videos = Vec::new();
+    for item in items.as_array().into_iter().flatten() {
+        if item["type"].as_str().is_some_and(|t| t != "stream") {
+            continue;
+        }
+        match parse_stream(item, tab) {
+            Ok(video) => videos.push(video),
+            Err(err) => skip_item(err, skipped),
+        }
+    }
+    videos
+}
+
+fn parse_stream(item: &Value, tab: Tab) -> Result<VideoInfo, ScrapeError> {
+    let url = str_at(item, "/url")?;
+    let id = url
+        .strip_prefix("/watch?v=")
+        .ok_or_else(|| ScrapeError::unexpected("/url", url))?
+        .to_string();
+    // uploaded is in milliseconds, and -1 if unknown
+    let published = item["uploaded"]
+        .as_i64()
+        .filter(|ms| *ms > 0)
+        .and_then(DateTime::from_timestamp_millis)
+        .map(|dt| dt.fixed_offset());
+    let kind = if item["isShort"].as_bool() == Some(true) {
+        VideoKind::Short
+    } else if item["duration"].as_i64() == Some(-1) {
+        VideoKind::Live
+    } else if published.is_some_and(|p| p > Utc::now()) {
+        VideoKind::Upcoming
+    } else {
+        tab.default_kind()
+    };
+    let duration = match item["duration"].as_u64() {
+        Some(secs) if kind.has_duration() => Duration::from_secs(secs),
+        _ => Duration::ZERO,
+    };
+    Ok(VideoInfo {
+        id,
+        duration,
+        kind,
+        title: item["title"].as_str().map(str::to_string),
+        views: item["views"].as_u64(),
+        published,
+        description: item["shortDescription"].as_str().map(str::to_string),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub;
+
+    const ROUTES: &[(&str, &str)] = &[
+        ("/@/piped", "piped_channel.json"),
+        ("/channel/UCpiped00000000000000000", "piped_channel.json"),
+        (
+            "/nextpage/channel/UCpiped00000000000000000?nextpage=page2",
+            "piped_nextpage.json",
+        ),
+        ("/channels/tabs?data=shorts-tab", "piped_shorts.json"),
+    ];
+
+    /// The videos and shorts tabs are empty and only link to themselves
+    const ENDLESS_ROUTES: &[(&str, &str)] = &[
+        (
+            "/channel/UCpipedEndless0000000000",
+            "piped_channel_endless.json",
+        ),
+        (
+            "/nextpage/channel/UCpipedEndless0000000000?nextpage=endless",
+            "piped_endless.json",
+        ),
+        ("/channels/tabs?data=endless-tab", "piped_endless.json"),
+        (
+            "/channels/tabs?data=endless-tab&nextpage=endless",
+            "piped_endless.json",
+        ),
+    ];
+
+    fn ids(extraction: &Extraction) -> Vec<&str> {
+        extraction.videos.iter().map(|v| v.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn channel() {
+        let url = stub::serve(ROUTES).await;
+        let extractor = PipedExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("@piped", &[Tab::Videos, Tab::Shorts], 30)
+            .await
+            .unwrap();
+
+        assert_eq!(extraction.channel.id, "UCpiped00000000000000000");
+        assert_eq!(extraction.channel.title, "Piped Channel");
+        // the channel item is left out, the item with a playlist URL is skipped
+        assert_eq!(
+            ids(&extraction),
+            [
+                "vidPiped001",
+                "vidPiped002",
+                "vidPiped003",
+                "vidPiped004",
+                "vidPiped005",
+                "vidPiped006"
+            ]
+        );
+        assert_eq!(extraction.skipped, 1);
+        let kinds: Vec<VideoKind> = extraction.videos.iter().map(|v| v.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                VideoKind::Video,
+                VideoKind::Live,
+                VideoKind::Upcoming,
+                VideoKind::Short,
+                VideoKind::Video,
+                VideoKind::Short
+            ]
+        );
+
+        let video = &extraction.videos[0];
+        assert_eq!(video.duration, Duration::from_secs(754));
+        assert_eq!(video.views, Some(1234));
+        assert_eq!(
+            video.published,
+            DateTime::from_timestamp(1_700_000_000, 0).map(|dt| dt.fixed_offset())
+        );
+        assert_eq!(video.description.as_deref(), Some("First line"));
+        let live = &extraction.videos[1];
+        assert_eq!(live.duration, Duration::ZERO);
+        assert_eq!(live.published, None);
+        assert_eq!(extraction.videos[3].duration, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn max_videos() {
+        let url = stub::serve(ROUTES).await;
+        let extractor = PipedExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel("channel/UCpiped00000000000000000", &[Tab::Videos], 2)
+            .await
+            .unwrap();
+        assert_eq!(ids(&extraction), ["vidPiped001", "vidPiped002"]);
+    }
+
+    #[tokio::test]
+    async fn endless_continuation() {
+        let url = stub::serve(ENDLESS_ROUTES).await;
+        let extractor = PipedExtractor::new(&url, Client::new());
+        let extraction = extractor
+            .extract_channel(
+                "channel/UCpipedEndless0000000000",
+                &[Tab::Videos, Tab::Shorts],
+                30,
+            )
+            .await
+            .unwrap();
+        assert!(extraction.videos.is_empty());
+    }
+
+    #[tokio::test]
+    async fn channel_id() {
+        let url = stub::serve(ROUTES).await;
+        let extractor = PipedExtractor::new(&url, Client::new());
+        assert_eq!(
+            extractor.extract_channel_id("@piped").await.unwrap(),
+            "UCpiped00000000000000000"
+        );
+        assert!(matches!(
+            extractor.extract_channel_id("@missing").await,
+            Err(Error::ChannelNotFound(_))
+        ));
+    }
+}
@@ -1,33 +1,194 @@
-use crate::extractor::VideoInfo;
+use crate::{
+    error::{Error, ScrapeError},
+    extractor::{VideoInfo, VideoKind},
+    media::MediaFile,
+};
 use atom_syndication::{
-    Entry, EntryBuilder, Feed as AtomFeed, FeedBuilder, LinkBuilder, PersonBuilder, Text,
+    extension::Extension, CategoryBuilder, ContentBuilder, Entry, EntryBuilder, Feed as AtomFeed,
+    FeedBuilder, LinkBuilder, PersonBuilder, Text,
+};
+use chrono::{DateTime, FixedOffset};
+use rss::{
+    extension::{
+        atom::AtomExtensionBuilder,
+        dublincore::DublinCoreExtensionBuilder,
+        itunes::{ITunesChannelExtensionBuilder, ITunesItemExtensionBuilder},
+        Extension as RssExtension,
+    },
+    CategoryBuilder as RssCategoryBuilder, Channel as RssChannel, ChannelBuilder, EnclosureBuilder,
+    GuidBuilder, Item, ItemBuilder,
 };
-use chrono::{DateTime, FixedOffset, Utc};
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, time::Duration};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Feed {
     pub channel: Channel,
     pub videos: Vec<Video>,
+    /// Number of items on the channel page that were skipped because they couldn't be parsed
+    #[serde(default)]
+    pub skipped: usize,
+    /// When the videos were fetched, which is the update time of a feed without videos
+    #[serde(default = "unix_epoch")]
+    pub fetched: DateTime<FixedOffset>,
 }
 
-impl Feed {
-    pub fn into_atom(self, base_url: &str, handle: &str, query_string: &str) -> AtomFeed {
-        let mut self_link = base_url.to_string() + "@" + handle;
-        if !query_string.is_empty() {
-            self_link.push('?');
-            self_link.push_str(query_string);
+/// Update time of feeds that were cached before the fetch time was stored
+fn unix_epoch() -> DateTime<FixedOffset> {
+    DateTime::UNIX_EPOCH.fixed_offset()
+}
+
+/// Output format of a feed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Atom,
+    Rss,
+    Json,
+    /// RSS with audio enclosures from the media directory
+    Podcast,
+}
+
+impl Format {
+    /// Registered MIME type of the format
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            Format::Atom => "application/atom+xml",
+            Format::Rss | Format::Podcast => "application/rss+xml",
+            Format::Json => "application/feed+json",
+        }
+    }
+
+    fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "application/atom+xml" => Some(Format::Atom),
+            "application/rss+xml" => Some(Format::Rss),
+            "application/feed+json" | "application/json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    /// Picks the format and MIME type of a response
+    ///
+    /// The `format` query parameter overrides the `Accept` header, otherwise the feed type with
+    /// the highest quality in the `Accept` header is used. XML feeds are served as `text/xml`,
+    /// which is more widely supported, unless the client accepts their registered MIME type.
+    pub fn negotiate(format: Option<Format>, accept: Option<&str>) -> (Self, &'static str) {
+        let accepted: Vec<(String, f32)> = accept
+            .map(media_ranges)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        let format = format
+            .or_else(|| {
+                accepted
+                    .iter()
+                    .filter_map(|(mime_type, quality)| {
+                        Some((Format::from_mime_type(mime_type)?, *quality))
+                    })
+                    // the first of the highest quality wins
+                    .fold(
+                        None,
+                        |best: Option<(Format, f32)>, (format, quality)| match best {
+                            Some((_, best_quality)) if best_quality >= quality => best,
+                            _ => Some((format, quality)),
+                        },
+                    )
+                    .map(|(format, _)| format)
+            })
+            .unwrap_or_default();
+        let accepts_mime_type = accepted
+            .iter()
+            .any(|(mime_type, _)| mime_type == format.mime_type());
+        let content_type = if format == Format::Json || accepts_mime_type {
+            format.mime_type()
+        } else {
+            "text/xml"
         };
+        (format, content_type)
+    }
+}
+
+/// Parses the media ranges of an `Accept` header with their quality values
+fn media_ranges(accept: &str) -> Vec<(String, f32)> {
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let mime_type = params.next()?.trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|quality| quality.parse().ok())
+                .unwrap_or(1.0);
+            (!mime_type.is_empty()).then_some((mime_type, quality))
+        })
+        .collect()
+}
+
+impl Feed {
+    /// Merges feeds of several channels into one feed, newest videos first
+    ///
+    /// Videos keep the channel they are from as author. Videos that are in more than one feed,
+    /// e.g. in overlapping playlists, are included once.
+    pub fn merge(channel: Channel, feeds: Vec<Feed>) -> Self {
+        let mut videos: Vec<Video> = Vec::new();
+        let mut skipped = 0;
+        let mut fetched = unix_epoch();
+        for feed in feeds {
+            skipped += feed.skipped;
+            fetched = fetched.max(feed.fetched);
+            for mut video in feed.videos {
+                if !videos.iter().any(|v| v.id == video.id) {
+                    video.author.get_or_insert_with(|| feed.channel.clone());
+                    videos.push(video);
+                }
+            }
+        }
+        videos.sort_by_key(|v| std::cmp::Reverse(v.published));
+        Self {
+            channel,
+            videos,
+            skipped,
+            fetched,
+        }
+    }
+
+    /// Time of the latest video update, or the fetch time if there are no videos, so that the
+    /// feed renders the same until it is fetched again
+    pub fn updated(&self) -> DateTime<FixedOffset> {
+        self.videos
+            .iter()
+            .map(|v| v.updated)
+            .max()
+            .unwrap_or(self.fetched)
+    }
+
+    /// Renders the feed in the given format
+    pub fn render(
+        self,
+        format: Format,
+        base_url: &str,
+        path: &str,
+        query_string: &str,
+    ) -> Result<String, Error> {
+        Ok(match format {
+            Format::Atom => self.into_atom(base_url, path, query_string).to_string(),
+            Format::Rss => self.into_rss(base_url, path, query_string).to_string(),
+            Format::Json => {
+                serde_json::to_string(&self.into_json_feed(base_url, path, query_string))?
+            }
+            Format::Podcast => self.into_podcast(base_url, path, query_string).to_string(),
+        })
+    }
+
+    pub fn into_atom(self, base_url: &str, path: &str, query_string: &str) -> AtomFeed {
+        let self_link = self_link(base_url, path, query_string);
 
         FeedBuilder::default()
             .title(self.channel.title.clone())
-            .updated(
-                self.videos
-                    .iter()
-                    .map(|v| v.updated)
-                    .max()
-                    .unwrap_or_else(|| Utc::now().fixed_offset()),
-            )
+            .updated(self.updated())
             .author(
                 PersonBuilder::default()
                     .name(self.channel.title)
@@ -36,6 +197,7 @@ impl Feed {
             )
             .id(self_link.clone())
             .link(LinkBuilder::default().href(self_link).rel("self").build())
+            .namespace(("media".to_string(), MEDIA_NAMESPACE.to_string()))
             .entries(
                 self.videos
                     .into_iter()
@@ -43,74 +205,810 @@ impl Feed {
                         EntryBuilder::default()
                             .link(
                                 LinkBuilder::default()
-                                    .href(format!("https://www.youtube.com/watch?v={}", v.id))
+                                    .href(v.url())
                                     .rel("alternate")
                                     .build(),
                             )
-                            .id(v.id)
-                            .title(v.title)
+                            .title(v.kind.title_prefix().to_string() + &v.title)
+                            .category(CategoryBuilder::default().term(v.kind.as_str()).build())
+                            .authors(
+                                v.author
+                                    .iter()
+                                    .map(|author| {
+                                        PersonBuilder::default()
+                                            .name(author.title.clone())
+                                            .uri(Some(author.url.clone()))
+                                            .build()
+                                    })
+                                    .collect::<Vec<_>>(),
+                            )
                             .updated(v.updated)
                             .published(v.published)
-                            .summary(Text::plain(v.description))
+                            .summary(Text::plain(v.summary()))
+                            .content(
+                                ContentBuilder::default()
+                                    .content_type(Some("html".to_string()))
+                                    .value(Some(v.content_html()))
+                                    .build(),
+                            )
+                            .extensions(media_extensions(&v.thumbnail, |name, attrs| Extension {
+                                name,
+                                attrs,
+                                ..Default::default()
+                            }))
+                            .id(v.id)
                             .build()
                     })
                     .collect::<Vec<Entry>>(),
             )
             .build()
     }
+
+    pub fn into_rss(self, base_url: &str, path: &str, query_string: &str) -> RssChannel {
+        let self_link = self_link(base_url, path, query_string);
+        let last_build_date = self.updated();
+
+        ChannelBuilder::default()
+            .title(self.channel.title.clone())
+            .link(self.channel.url)
+            .description(format!("YouTube videos of {}", self.channel.title))
+            .last_build_date(last_build_date.to_rfc2822())
+            .namespace(("media".to_string(), MEDIA_NAMESPACE.to_string()))
+            .atom_ext(
+                AtomExtensionBuilder::default()
+                    .link(LinkBuilder::default().href(self_link).rel("self").build())
+                    .build(),
+            )
+            .items(
+                self.videos
+                    .into_iter()
+                    .map(|v| {
+                        ItemBuilder::default()
+                            .title(v.kind.title_prefix().to_string() + &v.title)
+                            .link(v.url())
+                            .category(RssCategoryBuilder::default().name(v.kind.as_str()).build())
+                            .pub_date(v.published.to_rfc2822())
+                            .description(v.summary())
+                            .content(v.content_html())
+                            .dublin_core_ext(v.author.as_ref().map(|author| {
+                                DublinCoreExtensionBuilder::default()
+                                    .creator(author.title.clone())
+                                    .build()
+                            }))
+                            .extensions(media_extensions(&v.thumbnail, |name, attrs| {
+                                RssExtension {
+                                    name,
+                                    attrs,
+                                    ..Default::default()
+                                }
+                            }))
+                            .guid(GuidBuilder::default().value(v.id).permalink(false).build())
+                            .build()
+                    })
+                    .collect::<Vec<Item>>(),
+            )
+            .build()
+    }
+
+    /// Renders an iTunes compatible podcast, with only the videos that have an audio file
+    pub fn into_podcast(self, base_url: &str, path: &str, query_string: &str) -> RssChannel {
+        let title = self.channel.title.clone();
+        let image = self.videos.first().map(|v| v.thumbnail.url.clone());
+        // the items are added below, the channel is updated with the latest video
+        let mut channel = Feed {
+            fetched: self.updated(),
+            channel: self.channel,
+            videos: Vec::new(),
+            skipped: self.skipped,
+        }
+        .into_rss(base_url, path, query_string);
+        channel.set_itunes_ext(
+            ITunesChannelExtensionBuilder::default()
+                .author(title.clone())
+                .summary(format!("YouTube videos of {title}"))
+                .image(image)
+                .explicit("false".to_string())
+                .build(),
+        );
+        channel.set_items(
+            self.videos
+                .into_iter()
+                .filter_map(|v| {
+                    let audio = v.audio.as_ref()?;
+                    let enclosure = EnclosureBuilder::default()
+                        .url(format!("{}media/{}", base_url, audio.file_name))
+                        .length(audio.length.to_string())
+                        .mime_type(audio.mime_type)
+                        .build();
+                    let author = v.author.as_ref().map_or(&title, |author| &author.title);
+                    let itunes = ITunesItemExtensionBuilder::default()
+                        .author(author.clone())
+                        .duration(v.duration.as_secs().to_string())
+                        .image(v.thumbnail.url.clone())
+                        .summary(v.summary())
+                        .episode_type("full".to_string())
+                        .build();
+                    Some(
+                        ItemBuilder::default()
+                            .title(v.title.clone())
+                            .link(v.url())
+                            .pub_date(v.published.to_rfc2822())
+                            .description(v.summary())
+                            .enclosure(enclosure)
+                            .itunes_ext(itunes)
+                            .guid(GuidBuilder::default().value(v.id).permalink(false).build())
+                            .build(),
+                    )
+                })
+                .collect::<Vec<Item>>(),
+        );
+        channel
+    }
+
+    pub fn into_json_feed(self, base_url: &str, path: &str, query_string: &str) -> JsonFeed {
+        JsonFeed {
+            version: "https://jsonfeed.org/version/1.1",
+            title: self.channel.title.clone(),
+            home_page_url: self.channel.url.clone(),
+            feed_url: self_link(base_url, path, query_string),
+            authors: vec![JsonAuthor {
+                name: self.channel.title,
+                url: self.channel.url,
+            }],
+            items: self
+                .videos
+                .into_iter()
+                .map(|v| JsonItem {
+                    authors: v
+                        .author
+                        .iter()
+                        .map(|author| JsonAuthor {
+                            name: author.title.clone(),
+                            url: author.url.clone(),
+                        })
+                        .collect(),
+                    url: v.url(),
+                    title: v.kind.title_prefix().to_string() + &v.title,
+                    content_text: v.summary(),
+                    content_html: v.content_html(),
+                    image: v.thumbnail.url.clone(),
+                    date_published: v.published.to_rfc3339(),
+                    date_modified: v.updated.to_rfc3339(),
+                    tags: vec![v.kind.as_str()],
+                    ytfeed: JsonVideo {
+                        about: env!("CARGO_PKG_HOMEPAGE"),
+                        kind: v.kind,
+                        duration: v.duration.as_secs(),
+                        views: v.views,
+                        likes: v.likes,
+                        published: v.published.to_rfc3339(),
+                        updated: v.updated.to_rfc3339(),
+                        estimated: v.estimated,
+                    },
+                    id: v.id,
+                })
+                .collect(),
+            ytfeed: JsonFeedInfo {
+                about: env!("CARGO_PKG_HOMEPAGE"),
+                skipped: self.skipped,
+            },
+        }
+    }
+}
+
+/// A feed in the [JSON Feed 1.1](https://jsonfeed.org/version/1.1) format
+#[derive(Debug, Serialize)]
+pub struct JsonFeed {
+    version: &'static str,
+    title: String,
+    home_page_url: String,
+    feed_url: String,
+    authors: Vec<JsonAuthor>,
+    items: Vec<JsonItem>,
+    #[serde(rename = "_ytfeed")]
+    ytfeed: JsonFeedInfo,
+}
+
+/// Feed data as a JSON Feed extension
+#[derive(Debug, Serialize)]
+struct JsonFeedInfo {
+    about: &'static str,
+    /// Items of the channel page that couldn't be parsed and are missing from the feed
+    skipped: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonAuthor {
+    name: String,
+    url: String,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonItem {
+    id: String,
+    url: String,
+    title: String,
+    content_text: String,
+    content_html: String,
+    image: String,
+    date_published: String,
+    date_modified: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    authors: Vec<JsonAuthor>,
+    tags: Vec<&'static str>,
+    #[serde(rename = "_ytfeed")]
+    ytfeed: JsonVideo,
+}
+
+/// Video data as a JSON Feed extension, so that clients don't have to parse the description
+#[derive(Debug, Serialize)]
+struct JsonVideo {
+    about: &'static str,
+    kind: VideoKind,
+    /// Duration in seconds, zero if it is not known
+    duration: u64,
+    views: u64,
+    likes: Option<u64>,
+    published: String,
+    updated: String,
+    /// Whether the publish date and views are estimated, see [`Video::estimated`]
+    estimated: bool,
 }
 
-#[derive(Debug, Clone)]
+/// Namespace of the Media RSS elements, which YouTube also uses in its feeds
+const MEDIA_NAMESPACE: &str = "http://search.yahoo.com/mrss/";
+
+/// `media:thumbnail` and `media:content` elements for the thumbnail of a video, so that feed
+/// readers show a preview
+///
+/// The Atom and RSS crates have the same extension map layout, but their own element type, which
+/// is built by `element` from a name and attributes.
+fn media_extensions<E>(
+    thumbnail: &Thumbnail,
+    element: impl Fn(String, BTreeMap<String, String>) -> E,
+) -> BTreeMap<String, BTreeMap<String, Vec<E>>> {
+    let attrs = BTreeMap::from([
+        ("url".to_string(), thumbnail.url.clone()),
+        ("width".to_string(), thumbnail.width.to_string()),
+        ("height".to_string(), thumbnail.height.to_string()),
+    ]);
+    let mut content_attrs = attrs.clone();
+    content_attrs.insert("medium".to_string(), "image".to_string());
+    content_attrs.insert("type".to_string(), "image/jpeg".to_string());
+    BTreeMap::from([(
+        "media".to_string(),
+        BTreeMap::from([
+            (
+                "thumbnail".to_string(),
+                vec![element("media:thumbnail".to_string(), attrs)],
+            ),
+            (
+                "content".to_string(),
+                vec![element("media:content".to_string(), content_attrs)],
+            ),
+        ]),
+    )])
+}
+
+/// Link to the feed itself, including the filter options
+fn self_link(base_url: &str, path: &str, query_string: &str) -> String {
+    let mut self_link = base_url.to_string() + path;
+    if !query_string.is_empty() {
+        self_link.push('?');
+        self_link.push_str(query_string);
+    };
+    self_link
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Channel {
     pub title: String,
     pub id: String,
     pub url: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Video {
     pub id: String,
     pub published: DateTime<FixedOffset>,
     pub updated: DateTime<FixedOffset>,
     pub title: String,
     pub description: String,
+    /// Statistics line shown above the description, set by the filter
+    pub stats: Option<String>,
+    pub thumbnail: Thumbnail,
+    /// Audio file in the media directory, only looked up for podcasts
+    #[serde(skip)]
+    pub audio: Option<MediaFile>,
+    /// Channel of the video in merged feeds, otherwise it is the channel of the feed
+    pub author: Option<Channel>,
     pub duration: Duration,
+    pub kind: VideoKind,
     pub likes: Option<u64>,
     pub views: u64,
+    /// Whether the video was backfilled from the channel page instead of the RSS feed, the publish
+    /// date and views are then estimated and the description and likes are missing
+    pub estimated: bool,
 }
 
 impl Video {
-    pub fn from_entry_and_info(entry: Entry, info: VideoInfo) -> Self {
-        let group = &entry.extensions["media"]["group"][0];
-        let community = &group.children["community"][0];
-        let likes: Option<u64> = community
-            .children
-            .get("starRating")
-            .map(|e| e[0].attrs["count"].parse().unwrap());
-        let views: u64 = community.children["statistics"][0].attrs["views"]
-            .parse()
-            .unwrap_or_else(|_| {
-                tracing::warn!("failed to parse media field 'views'");
-                0
+    /// Builds a video from an entry of the RSS feed and the extracted information, fails if the
+    /// entry has no publish date or media group
+    pub fn from_entry_and_info(entry: Entry, info: VideoInfo) -> Result<Self, ScrapeError> {
+        let published = entry
+            .published
+            .ok_or_else(|| ScrapeError::missing("published"))?;
+        let group = entry
+            .extensions
+            .get("media")
+            .and_then(|media| media.get("group"))
+            .and_then(|group| group.first())
+            .ok_or_else(|| ScrapeError::missing("media:group"))?;
+        let community = child(group, "community");
+        let likes = match parse_count(community, "starRating", "count") {
+            Ok(likes) => Some(likes),
+            // videos with ratings disabled and shorts don't have a star rating
+            Err(ScrapeError::Missing { .. }) => None,
+            Err(err) => {
+                tracing::warn!("failed to parse likes of '{}': {err}", info.id);
+                None
+            }
+        };
+        let views = parse_count(community, "statistics", "views").unwrap_or_else(|err| {
+            tracing::warn!("failed to parse views of '{}': {err}", info.id);
+            0
+        });
+        let title = get_media_field(group, "title");
+        let description = get_media_field(group, "description");
+        let thumbnail = child(group, "thumbnail")
+            .and_then(Thumbnail::from_extension)
+            .unwrap_or_else(|| {
+                tracing::warn!("failed to parse thumbnail of '{}'", info.id);
+                Thumbnail::default_for(&info.id)
             });
-        let title = get_media_field(&group.children["title"][0].value, "title");
-        let description = get_media_field(&group.children["description"][0].value, "description");
-        Self {
-            id: info.id,
-            published: entry.published.unwrap(),
+        Ok(Self {
+            published,
             updated: entry.updated,
             title,
             description,
+            stats: None,
+            thumbnail,
+            audio: None,
+            author: None,
             duration: info.duration,
+            kind: info.kind,
             likes,
             views,
+            estimated: false,
+            id: info.id,
+        })
+    }
+
+    /// Builds a video from the extracted information alone, for videos that are not in the RSS
+    /// feed or if the feed wasn't fetched. Returns `None` if the title or publish date are missing.
+    pub fn from_info(info: VideoInfo, estimated: bool) -> Option<Self> {
+        let published = info.published?;
+        Some(Self {
+            published,
+            updated: published,
+            title: info.title?,
+            description: info.description.unwrap_or_default(),
+            stats: None,
+            thumbnail: Thumbnail::default_for(&info.id),
+            audio: None,
+            author: None,
+            duration: info.duration,
+            kind: info.kind,
+            likes: None,
+            views: info.views.unwrap_or(0),
+            estimated,
+            id: info.id,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.id)
+    }
+
+    /// Plain text summary with the statistics and the description
+    pub fn summary(&self) -> String {
+        match &self.stats {
+            Some(stats) if self.description.is_empty() => stats.clone(),
+            Some(stats) => format!("{stats}\n\n{}", self.description),
+            None => self.description.clone(),
+        }
+    }
+
+    /// HTML content with the thumbnail, the statistics and the description
+    pub fn content_html(&self) -> String {
+        let mut html = format!(
+            r#"<a href="{}"><img src="{}" width="{}" height="{}" alt=""></a>"#,
+            self.url(),
+            escape_html(&self.thumbnail.url),
+            self.thumbnail.width,
+            self.thumbnail.height
+        );
+        if let Some(stats) = &self.stats {
+            html += &format!("<p>{}</p>", escape_html(stats));
+        }
+        for paragraph in self.description.split("\n\n") {
+            let paragraph = paragraph.trim();
+            if !paragraph.is_empty() {
+                html += &format!("<p>{}</p>", escape_html(paragraph).replace('\n', "<br>"));
+            }
+        }
+        html
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Thumbnail {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Thumbnail {
+    /// The `hqdefault` thumbnail, which YouTube has for every video
+    pub fn default_for(video_id: &str) -> Self {
+        Self {
+            url: format!("https://i.ytimg.com/vi/{video_id}/hqdefault.jpg"),
+            width: 480,
+            height: 360,
         }
     }
+
+    /// Parses a `media:thumbnail` element
+    fn from_extension(element: &Extension) -> Option<Self> {
+        Some(Self {
+            url: element.attrs.get("url")?.clone(),
+            width: element.attrs.get("width")?.parse().ok()?,
+            height: element.attrs.get("height")?.parse().ok()?,
+        })
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
 }
 
-fn get_media_field(option: &Option<String>, field_name: &str) -> String {
-    option.as_ref().map(|s| s.to_string()).unwrap_or_else(|| {
+fn get_media_field(group: &Extension, field_name: &str) -> String {
+    let value = child(group, field_name).and_then(|e| e.value.as_ref());
+    value.map(|s| s.to_string()).unwrap_or_else(|| {
         tracing::warn!("Failed to parse media field '{}'", field_name);
         format!("ytfeed: Failed to parse field '{}'", field_name)
     })
 }
+
+/// Gets the first child element of an extension element by name
+fn child<'a>(extension: &'a Extension, name: &str) -> Option<&'a Extension> {
+    extension.children.get(name).and_then(|c| c.first())
+}
+
+/// Parses a count attribute of a `media:community` element, e.g. `<media:statistics views="123"/>`
+fn parse_count(
+    community: Option<&Extension>,
+    element: &str,
+    attr: &str,
+) -> Result<u64, ScrapeError> {
+    let path = format!("media:community/media:{element}/@{attr}");
+    let value = community
+        .and_then(|c| child(c, element))
+        .and_then(|e| e.attrs.get(attr))
+        .ok_or_else(|| ScrapeError::missing(path.clone()))?;
+    value
+        .parse()
+        .map_err(|_| ScrapeError::unexpected(path, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{conditional::Validators, stub};
+    use axum::http::{header::ETAG, response::Builder};
+
+    fn info(id: &str) -> VideoInfo {
+        VideoInfo {
+            id: id.to_string(),
+            duration: Duration::from_secs(754),
+            kind: VideoKind::Video,
+            title: None,
+            views: None,
+            published: None,
+            description: None,
+        }
+    }
+
+    fn entry(feed: &AtomFeed, id: &str) -> Entry {
+        feed.entries
+            .iter()
+            .find(|e| e.extensions["yt"]["videoId"][0].value.as_deref() == Some(id))
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn entry_and_info() {
+        let feed = AtomFeed::read_from(stub::fixture("feed_lockup.xml").as_bytes()).unwrap();
+
+        let video =
+            Video::from_entry_and_info(entry(&feed, "vidLockup01"), info("vidLockup01")).unwrap();
+        assert_eq!(video.id, "vidLockup01");
+        assert_eq!(video.title, "Regular video");
+        assert_eq!(
+            video.description,
+            "A regular video\nwith a multi-line description"
+        );
+        assert_eq!(video.published.to_rfc3339(), "2026-10-15T09:30:00+00:00");
+        assert_eq!(video.updated.to_rfc3339(), "2026-10-15T10:00:00+00:00");
+        assert_eq!(video.duration, Duration::from_secs(754));
+        assert_eq!(video.likes, Some(5432));
+        assert_eq!(video.views, 1_234_567);
+        assert_eq!(
+            video.thumbnail.url,
+            "https://i3.ytimg.com/vi/vidLockup01/hqdefault.jpg"
+        );
+        assert_eq!((video.thumbnail.width, video.thumbnail.height), (480, 360));
+        assert!(!video.estimated);
+
+        // shorts don't have ratings
+        let short =
+            Video::from_entry_and_info(entry(&feed, "vidShort001"), info("vidShort001")).unwrap();
+        assert_eq!(short.likes, None);
+        assert_eq!(short.views, 98765);
+    }
+
+    fn live_feed() -> Feed {
+        let published = DateTime::parse_from_rfc3339("2026-10-15T09:30:00+00:00").unwrap();
+        Feed {
+            channel: Channel {
+                title: "Lockup Layout".to_string(),
+                id: "UClockupLayout00000000000".to_string(),
+                url: "https://www.youtube.com/channel/UClockupLayout00000000000".to_string(),
+            },
+            videos: vec![Video {
+                id: "vidLockup02".to_string(),
+                published,
+                updated: published,
+                title: "Live now".to_string(),
+                description: "Streaming right now".to_string(),
+                stats: None,
+                thumbnail: Thumbnail::default_for("vidLockup02"),
+                audio: None,
+                author: None,
+                duration: Duration::ZERO,
+                kind: VideoKind::Live,
+                likes: None,
+                views: 0,
+                estimated: false,
+            }],
+            skipped: 0,
+            fetched: DateTime::parse_from_rfc3339("2026-10-15T10:00:00+00:00").unwrap(),
+        }
+    }
+
+    #[test]
+    fn atom_media() {
+        let mut feed = live_feed();
+        feed.videos[0].stats = Some("👀 0 views".to_string());
+        feed.videos[0].description = "Join <here>\nnow\n\nBye & see you".to_string();
+        let atom = feed
+            .into_atom("http://localhost:8000/", "@lockup", "")
+            .to_string();
+
+        assert!(atom.contains(r#"xmlns:media="http://search.yahoo.com/mrss/""#));
+        assert!(atom.contains(
+            r#"<media:thumbnail height="360" url="https://i.ytimg.com/vi/vidLockup02/hqdefault.jpg" width="480">"#
+        ));
+        let entry = AtomFeed::read_from(atom.as_bytes()).unwrap().entries[0].clone();
+        assert_eq!(
+            entry.summary().unwrap().value,
+            "👀 0 views\n\nJoin <here>\nnow\n\nBye & see you"
+        );
+        assert_eq!(
+            entry.content().unwrap().value().unwrap(),
+            r#"<a href="https://www.youtube.com/watch?v=vidLockup02"><img src="https://i.ytimg.com/vi/vidLockup02/hqdefault.jpg" width="480" height="360" alt=""></a><p>👀 0 views</p><p>Join &lt;here&gt;<br>now</p><p>Bye &amp; see you</p>"#
+        );
+    }
+
+    #[test]
+    fn rss() {
+        let rss = live_feed().into_rss("http://localhost:8000/", "@lockup", "format=rss");
+
+        let self_link = &rss.atom_ext().unwrap().links()[0];
+        assert_eq!(self_link.href, "http://localhost:8000/@lockup?format=rss");
+        assert_eq!(self_link.rel, "self");
+        let item = &rss.items()[0];
+        assert_eq!(item.title(), Some("[Live] Live now"));
+        assert_eq!(item.guid().unwrap().value(), "vidLockup02");
+        assert_eq!(item.categories()[0].name(), "live");
+        assert_eq!(item.pub_date(), Some("Thu, 15 Oct 2026 09:30:00 +0000"));
+        assert_eq!(item.description(), Some("Streaming right now"));
+    }
+
+    #[test]
+    fn podcast() {
+        let mut feed = live_feed();
+        let mut video = feed.videos[0].clone();
+        video.id = "vidLockup01".to_string();
+        video.duration = Duration::from_secs(754);
+        video.audio = Some(MediaFile {
+            file_name: "vidLockup01.m4a".to_string(),
+            length: 1234,
+            mime_type: "audio/mp4",
+        });
+        feed.videos.push(video);
+        let podcast = feed.into_podcast("http://localhost:8000/", "@lockup", "format=podcast");
+
+        assert!(podcast.itunes_ext().is_some());
+        // only videos with an audio file are included
+        assert_eq!(podcast.items().len(), 1);
+        let item = &podcast.items()[0];
+        let enclosure = item.enclosure().unwrap();
+        assert_eq!(
+            enclosure.url(),
+            "http://localhost:8000/media/vidLockup01.m4a"
+        );
+        assert_eq!(enclosure.length(), "1234");
+        assert_eq!(enclosure.mime_type(), "audio/mp4");
+        assert_eq!(item.itunes_ext().unwrap().duration(), Some("754"));
+        assert_eq!(
+            podcast.last_build_date(),
+            Some("Thu, 15 Oct 2026 09:30:00 +0000")
+        );
+    }
+
+    #[test]
+    fn empty_feed() {
+        let empty = || {
+            let mut feed = live_feed();
+            feed.videos.clear();
+            feed
+        };
+        // the fetch time is used, so that the entity tag stays the same
+        for format in [Format::Atom, Format::Rss, Format::Json] {
+            let render = || {
+                let body = empty()
+                    .render(format, "http://localhost:8000/", "@lockup", "")
+                    .unwrap();
+                let validators = Validators::new(&body, Some(empty().updated()), None);
+                let response = validators.headers(Builder::new()).body(()).unwrap();
+                response.headers()[ETAG].clone()
+            };
+            assert_eq!(render(), render(), "{format:?}");
+        }
+        let atom = empty().into_atom("http://localhost:8000/", "@lockup", "");
+        assert_eq!(atom.updated(), &empty().fetched);
+        let rss = empty().into_rss("http://localhost:8000/", "@lockup", "");
+        assert_eq!(
+            rss.last_build_date(),
+            Some("Thu, 15 Oct 2026 10:00:00 +0000")
+        );
+    }
+
+    #[test]
+    fn json_feed() {
+        let json = live_feed()
+            .render(
+                Format::Json,
+                "http://localhost:8000/",
+                "@lockup",
+                "format=json",
+            )
+            .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(json["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(
+            json["feed_url"],
+            "http://localhost:8000/@lockup?format=json"
+        );
+        let item = &json["items"][0];
+        assert_eq!(item["id"], "vidLockup02");
+        assert_eq!(item["title"], "[Live] Live now");
+        assert_eq!(item["date_published"], "2026-10-15T09:30:00+00:00");
+        assert_eq!(item["_ytfeed"]["kind"], "live");
+        assert_eq!(item["_ytfeed"]["duration"], 0);
+        assert_eq!(item["_ytfeed"]["likes"], serde_json::Value::Null);
+        assert_eq!(json["_ytfeed"]["skipped"], 0);
+    }
+
+    #[test]
+    fn serde() {
+        // feeds are serialized in the cache directory
+        let feed = live_feed();
+        let json = serde_json::to_string(&feed).unwrap();
+        let parsed: Feed = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.videos[0].published, feed.videos[0].published);
+        assert_eq!(parsed.videos[0].kind, VideoKind::Live);
+        assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+    }
+
+    #[test]
+    fn merge() {
+        let other = |id: &str, published: &str| {
+            let mut feed = live_feed();
+            feed.channel.title = "Other".to_string();
+            feed.videos[0].id = id.to_string();
+            feed.videos[0].published = DateTime::parse_from_rfc3339(published).unwrap();
+            feed
+        };
+        let channel = Channel {
+            title: "Merged".to_string(),
+            id: "merge".to_string(),
+            url: "http://localhost:8000/merge".to_string(),
+        };
+        let mut feeds = vec![
+            live_feed(),
+            other("vidNewer0001", "2026-10-16T09:30:00+00:00"),
+            // also in the first feed
+            other("vidLockup02", "2026-10-15T09:30:00+00:00"),
+        ];
+        feeds[0].skipped = 1;
+        feeds[1].skipped = 2;
+        let merged = Feed::merge(channel, feeds);
+        assert_eq!(merged.skipped, 3);
+
+        let ids: Vec<&str> = merged.videos.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, ["vidNewer0001", "vidLockup02"]);
+        let authors: Vec<&str> = merged
+            .videos
+            .iter()
+            .map(|v| v.author.as_ref().unwrap().title.as_str())
+            .collect();
+        assert_eq!(authors, ["Other", "Lockup Layout"]);
+
+        let rss = merged
+            .clone()
+            .into_rss("http://localhost:8000/", "merge", "h=%40a")
+            .to_string();
+        assert!(rss.contains("<dc:creator>Other</dc:creator>"));
+        let atom = merged.into_atom("http://localhost:8000/", "merge", "h=%40a");
+        assert_eq!(atom.entries[1].authors[0].name, "Lockup Layout");
+    }
+
+    #[test]
+    fn accept() {
+        let negotiate = |accept| Format::negotiate(None, Some(accept));
+        assert_eq!(
+            negotiate("application/rss+xml, application/xml;q=0.9, */*;q=0.8"),
+            (Format::Rss, "application/rss+xml")
+        );
+        assert_eq!(
+            negotiate("application/atom+xml"),
+            (Format::Atom, "application/atom+xml")
+        );
+        assert_eq!(
+            negotiate("application/json"),
+            (Format::Json, "application/feed+json")
+        );
+        assert_eq!(
+            negotiate("application/rss+xml;q=0.5, application/feed+json;q=0.9"),
+            (Format::Json, "application/feed+json")
+        );
+        assert_eq!(
+            negotiate("application/atom+xml;q=0, application/rss+xml;q=0.1"),
+            (Format::Rss, "application/rss+xml")
+        );
+        assert_eq!(
+            negotiate("APPLICATION/ATOM+XML; q=1.0, application/rss+xml"),
+            (Format::Atom, "application/atom+xml")
+        );
+        // clients that don't specify a feed type
+        assert_eq!(negotiate("*/*"), (Format::Atom, "text/xml"));
+        assert_eq!(negotiate("text/xml, */*"), (Format::Atom, "text/xml"));
+        assert_eq!(Format::negotiate(None, None), (Format::Atom, "text/xml"));
+        // the query parameter overrides the header
+        assert_eq!(
+            Format::negotiate(Some(Format::Rss), Some("application/atom+xml")),
+            (Format::Rss, "text/xml")
+        );
+        assert_eq!(
+            Format::negotiate(Some(Format::Podcast), Some("application/rss+xml, */*")),
+            (Format::Podcast, "application/rss+xml")
+        );
+    }
+}
@@ -1,12 +1,13 @@
 use crate::{
     error::Error,
-    feed::{Feed, Video},
+    extractor::{Tab, VideoKind},
+    feed::{Feed, Format, Video},
 };
 use num_format::{Locale, ToFormattedString};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Filter {
     #[serde(rename = "d", default)]
     pub duration: Option<u64>,
@@ -16,21 +17,90 @@ pub struct Filter {
     pub likes: Option<u64>,
     #[serde(rename = "lvr", default, skip_serializing_if = "std::ops::Not::not")]
     pub like_view_ratio: bool,
+    /// Kinds of videos to include, only regular videos if empty
+    #[serde(rename = "k", default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<VideoKind>,
+    /// Include older videos that are not in the RSS feed, with estimated statistics
+    #[serde(rename = "b", default, skip_serializing_if = "std::ops::Not::not")]
+    pub backfill: bool,
+    /// Output format, taken from the `Accept` header if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<Format>,
 }
 
 impl Filter {
-    pub fn apply(&self, mut feed: Feed) -> Result<Feed, Error> {
+    /// Filters the videos of a feed, lines with one of the ad keywords are removed from the
+    /// descriptions
+    pub fn apply(&self, mut feed: Feed, ad_keywords: &[String]) -> Result<Feed, Error> {
         let orig_count = feed.videos.len();
-        feed.videos.retain_mut(|v| self.filter_video(v));
+        feed.videos
+            .retain_mut(|v| self.filter_video(v, ad_keywords));
         if orig_count != feed.videos.len() {
             tracing::debug!("filtered {} videos", orig_count - feed.videos.len());
         }
         Ok(feed)
     }
 
-    fn filter_video(&self, video: &mut Video) -> bool {
+    /// Fills in the options that are not set with the defaults, options that are on by default
+    /// can't be turned off
+    pub fn with_defaults(self, defaults: &Filter) -> Self {
+        Self {
+            duration: self.duration.or(defaults.duration),
+            views: self.views.or(defaults.views),
+            likes: self.likes.or(defaults.likes),
+            like_view_ratio: self.like_view_ratio || defaults.like_view_ratio,
+            kinds: if self.kinds.is_empty() {
+                defaults.kinds.clone()
+            } else {
+                self.kinds
+            },
+            backfill: self.backfill || defaults.backfill,
+            format: self.format.or(defaults.format),
+        }
+    }
+
+    /// Channel tabs that have to be scraped to find the included kinds of videos
+    pub fn tabs(&self) -> Vec<Tab> {
+        let kinds = self.kinds();
+        let mut tabs = Vec::new();
+        // premieres are shown on the videos tab
+        if kinds
+            .iter()
+            .any(|k| matches!(k, VideoKind::Video | VideoKind::Live | VideoKind::Upcoming))
+        {
+            tabs.push(Tab::Videos);
+        }
+        if kinds.iter().any(|k| {
+            matches!(
+                k,
+                VideoKind::Live | VideoKind::Upcoming | VideoKind::Archived
+            )
+        }) {
+            tabs.push(Tab::Streams);
+        }
+        if kinds.contains(&VideoKind::Short) {
+            tabs.push(Tab::Shorts);
+        }
+        tabs
+    }
+
+    fn kinds(&self) -> &[VideoKind] {
+        if self.kinds.is_empty() {
+            &[VideoKind::Video]
+        } else {
+            &self.kinds
+        }
+    }
+
+    fn filter_video(&self, video: &mut Video, ad_keywords: &[String]) -> bool {
+        if !self.kinds().contains(&video.kind) {
+            return false;
+        }
+        if video.estimated && !self.backfill {
+            return false;
+        }
         if let Some(min_duration) = &self.duration {
-            if video.duration.as_secs() < *min_duration {
+            if video.kind.has_duration() && video.duration.as_secs() < *min_duration {
                 return false;
             }
         }
@@ -50,23 +120,38 @@ impl Filter {
                 video.title = format!("{} [{:.1}]", video.title, lvr);
             }
         }
-        self.filter_description(video);
+        self.filter_description(video, ad_keywords);
         true
     }
 
-    fn filter_description(&self, video: &mut Video) {
-        let text = remove_ads(&video.description);
+    fn filter_description(&self, video: &mut Video, ad_keywords: &[String]) {
+        let text = remove_ads(&video.description, ad_keywords);
         let likes_text = video
             .likes
             .map(|l| format!(", 👍 {} likes", l.to_formatted_string(&Locale::en)))
             .unwrap_or_default();
+        let duration_text = if video.kind.has_duration() {
+            format!(", ⏲️  {}", format_duration(&video.duration))
+        } else {
+            String::new()
+        };
+        let estimated_text = if video.estimated { "~" } else { "" };
         let info_text = format!(
-            "👀 {} views{}, ⏲️  {}",
+            "👀 {}{} views{}{}",
+            estimated_text,
             video.views.to_formatted_string(&Locale::en),
             likes_text,
-            format_duration(&video.duration)
+            duration_text
         );
-        video.description = info_text + "\n\n" + &text;
+        video.stats = Some(info_text);
+        video.description = text;
+        if video.estimated {
+            if !video.description.is_empty() {
+                video.description += "\n\n";
+            }
+            video.description +=
+                "ytfeed: not in the RSS feed, the publish date and views are estimated";
+        }
     }
 
     pub fn query_string(&self) -> Result<String, Error> {
@@ -86,7 +171,8 @@ fn format_duration(d: &Duration) -> String {
     }
 }
 
-const AD_KEYWORDS: &[&str] = &[
+/// Default keywords of sponsor messages, the spaces match word boundaries
+pub const AD_KEYWORDS: &[&str] = &[
     " affiliate",
     " affordable ",
     " check out ",
@@ -117,16 +203,17 @@ const AD_KEYWORDS: &[&str] = &[
     "% off ",
 ];
 
-fn remove_ads(text: &str) -> String {
+/// Lowercases a description line or ad keyword and removes non-ASCII characters such as emojis,
+/// so that keywords match regardless of decoration
+pub fn normalize(text: &str) -> String {
+    text.to_lowercase().replace(|c: char| !c.is_ascii(), "")
+}
+
+fn remove_ads(text: &str, ad_keywords: &[String]) -> String {
     text.lines()
         .filter(|line| {
-            let normalized = " ".to_string()
-                + &line
-                    .trim()
-                    .to_lowercase()
-                    .replace(|c: char| !c.is_ascii(), "")
-                + " ";
-            for kw in AD_KEYWORDS {
+            let normalized = " ".to_string() + &normalize(line.trim()) + " ";
+            for kw in ad_keywords {
                 if normalized.contains(kw) {
                     return false;
                 }
@@ -138,3 +225,56 @@ fn remove_ads(text: &str) -> String {
         .trim()
         .to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::Thumbnail;
+    use chrono::DateTime;
+
+    fn video(description: &str) -> Video {
+        let published = DateTime::parse_from_rfc3339("2026-10-15T09:30:00+00:00").unwrap();
+        Video {
+            id: "vidInvid001".to_string(),
+            published,
+            updated: published,
+            title: "Backfilled video".to_string(),
+            description: description.to_string(),
+            stats: None,
+            thumbnail: Thumbnail::default_for("vidInvid001"),
+            audio: None,
+            author: None,
+            duration: Duration::from_secs(754),
+            kind: VideoKind::Video,
+            likes: None,
+            views: 1234,
+            estimated: true,
+        }
+    }
+
+    #[test]
+    fn estimated_note() {
+        let filter = Filter::default();
+        let mut estimated = video("From the playlist");
+        filter.filter_description(&mut estimated, &[]);
+        assert_eq!(
+            estimated.description,
+            "From the playlist\n\nytfeed: not in the RSS feed, the publish date and views are estimated"
+        );
+        assert_eq!(
+            estimated.stats.as_deref(),
+            Some("👀 ~1,234 views, ⏲️  12:34")
+        );
+
+        let mut estimated = video("");
+        filter.filter_description(&mut estimated, &[]);
+        assert!(estimated.description.starts_with("ytfeed:"));
+    }
+
+    #[test]
+    fn ads() {
+        let keywords = [normalize("Café"), " sponsor ".to_string()];
+        let text = "Intro\n☕ Visit the CAFÉ!\nThanks to our sponsor today\nOutro";
+        assert_eq!(remove_ads(text, &keywords), "Intro\nOutro");
+    }
+}
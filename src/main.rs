@@ -1,56 +1,61 @@
 mod cache;
+mod conditional;
+mod config;
 mod error;
 mod extractor;
 mod feed;
 mod filter;
+mod media;
+mod opml;
 mod proxy;
+#[cfg(test)]
+mod stub;
+mod takeout;
 
-use crate::{cache::Cache, error::Error};
+use crate::{
+    cache::{BoxFut, Cache, CacheError, Cached, Store},
+    conditional::Validators,
+    error::Error,
+};
 use axum::{
     body::Body,
-    extract::{Path, Query},
-    http::Request,
+    extract::Path,
+    http::{
+        header::{ACCEPT, CONTENT_DISPOSITION, CONTENT_TYPE, VARY, WARNING},
+        HeaderMap, Request,
+    },
     response::Response,
-    routing::get,
+    routing::{get, post},
     Extension, Router,
 };
-use clap::Parser;
-use feed::Feed;
+// unlike axum's query extractor, it supports repeated keys (e.g. `k=video&k=short`)
+use axum_extra::extract::Query;
+use config::{Command, Config};
+use extractor::YOUTUBE_URL;
+use feed::{Channel, Feed, Format};
 use filter::Filter;
+use futures::{stream, StreamExt};
+use media::MediaStore;
+use proxy::{FeedKey, FeedSource, Proxy};
 use reqwest::{Client, StatusCode};
-use std::{
-    collections::HashMap,
-    net::{IpAddr, Ipv4Addr, SocketAddr},
-    time::Duration,
-};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tokio::net::TcpListener;
-use tower_http::trace::TraceLayer;
-use tracing_subscriber::{fmt, prelude::*, EnvFilter};
-
-#[derive(Parser, Debug, Clone)]
-#[command(author, version, about)]
-struct Config {
-    /// Socket address
-    #[arg(short = 's', long = "socket", default_value_t = SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 8000))]
-    socket_addres: SocketAddr,
-    /// How long to keep feeds cached (in seconds)
-    #[arg(short = 'c', long = "cache", default_value_t = 300)]
-    cache_timeout: u64,
-    /// Base URL used in the feed
-    #[arg(
-        short = 'b',
-        long = "base_url",
-        default_value = "http://localhost:8000/"
-    )]
-    base_url: String,
-}
+use tower_http::{services::ServeDir, trace::TraceLayer};
+use tracing_subscriber::{fmt, fmt::writer::BoxMakeWriter, prelude::*, EnvFilter};
 
 #[tokio::main]
 async fn main() {
-    let config = Config::parse();
+    let config = Config::load();
 
+    // subcommands can write their output to stdout
+    let log_writer = if config.command.is_some() {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
     tracing_subscriber::registry()
-        .with(fmt::layer())
+        .with(fmt::layer().with_writer(log_writer))
         .with(
             EnvFilter::builder()
                 .with_default_directive("ytfeed=DEBUG".parse().unwrap())
@@ -58,30 +63,109 @@ async fn main() {
         )
         .init();
 
-    let socket_address = config.socket_addres;
+    let socket_address = config.socket_address;
 
-    let client = Client::builder()
+    let mut client = Client::builder()
         .brotli(true) // reduce bandwidth, youtube.com supports it
         .gzip(true)
-        .timeout(Duration::new(10, 0))
-        .build()
-        .unwrap();
+        .timeout(config.client_timeout);
+    if let Some(user_agent) = &config.user_agent {
+        client = client.user_agent(user_agent);
+    }
+    let client = client.build().unwrap();
+
+    let extractor = extractor::with_fallback(
+        config.extractor,
+        YOUTUBE_URL,
+        &config.instances,
+        client.clone(),
+    )
+    .unwrap_or_else(|err| {
+        Config::exit_with_error(clap::error::ErrorKind::MissingRequiredArgument, err)
+    });
+
+    let proxy = Proxy::new(client, extractor, config.max_videos, YOUTUBE_URL);
+
+    if let Some(Command::Import(args)) = config.command {
+        if let Err(err) = takeout::import(args, &proxy, &config.base_url).await {
+            tracing::error!("import failed: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
     let trace_layer = TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
         let uri = request.uri().to_string();
         tracing::info_span!("http_request", method = ?request.method(), uri)
     });
 
-    let router = Router::new()
+    let media = config.media_dir.map(MediaStore::new);
+
+    let mut feed_cache =
+        Cache::new(Some(config.cache_timeout)).with_error_timeouts(config.cache_error_timeouts);
+    if let Some(max_stale) = config.cache_stale_if_error {
+        feed_cache = feed_cache.with_stale_if_error(max_stale);
+    }
+    if let Some(max_stale) = config.cache_stale_while_revalidate {
+        feed_cache = feed_cache.with_stale_while_revalidate(max_stale);
+    }
+    // channel ids don't change, so resolved names are kept indefinitely
+    let channel_ids = Cache::new(None).with_error_timeouts(config.cache_error_timeouts);
+    let (channel_ids, feed_cache) = match &config.cache_dir {
+        Some(dir) => {
+            let store = |name| {
+                Store::new(&dir.join(name)).unwrap_or_else(|err| {
+                    Config::exit_with_error(
+                        clap::error::ErrorKind::Io,
+                        format!("failed to create cache dir {}: {err}", dir.display()),
+                    )
+                })
+            };
+            (
+                channel_ids.with_store(store("channels")),
+                feed_cache.with_store(store("feeds")),
+            )
+        }
+        None => (channel_ids, feed_cache),
+    };
+    let channel_ids = channel_ids.with_max_items(config.cache_max_items);
+    let feed_cache = feed_cache.with_max_items(config.cache_max_items);
+    // expired feeds and failed lookups are removed at least once per timeout
+    let sweep_interval = config.cache_timeout.max(Duration::from_secs(60));
+    channel_ids.spawn_sweeper(sweep_interval);
+    feed_cache.spawn_sweeper(sweep_interval);
+
+    let state = FeedState {
+        proxy,
+        channel_ids,
+        feed_cache,
+        base_url: config.base_url,
+        media: media.clone(),
+        groups: Arc::new(config.groups),
+        default_filter: Arc::new(config.default_filter),
+        ad_keywords: config.ad_keywords.into(),
+    };
+    if let Some(min_hits) = config.cache_refresh_min_hits {
+        let interval = (config.cache_timeout / 10).max(Duration::from_secs(10));
+        state.spawn_refresher(interval, min_hits);
+    }
+
+    let mut router = Router::new()
         .route("/@{handle}", get(get_feed))
+        .route("/channel/{id}", get(get_channel_feed))
+        .route("/c/{name}", get(get_custom_url_feed))
+        .route("/user/{name}", get(get_user_feed))
+        .route("/playlist/{id}", get(get_playlist_feed))
+        .route("/merge", get(get_merged_feed))
+        .route("/group/{name}", get(get_group_feed))
+        .route("/opml", post(convert_opml))
         .route("/health", get(get_health))
-        .layer(Extension(client))
-        .layer(Extension(HashMap::<String, String>::new()))
-        .layer(Extension(Cache::<String, Option<Feed>>::new(Some(
-            Duration::from_secs(config.cache_timeout),
-        ))))
-        .layer(Extension(config.base_url))
-        .layer(trace_layer);
+        .layer(Extension(state));
+    if let Some(media) = media {
+        // supports range requests, so that podcast players can seek
+        router = router.nest_service("/media", ServeDir::new(media.dir()));
+    }
+    let router = router.layer(trace_layer);
 
     tracing::info!("starting server at http://{}", socket_address);
 
@@ -92,44 +176,341 @@ async fn main() {
 async fn get_feed(
     Path(handle): Path<String>,
     Query(filter): Query<Filter>,
-    Extension(http_client): Extension<Client>,
-    Extension(feed_cache): Extension<Cache<String, Option<Feed>>>,
-    Extension(base_url): Extension<String>,
+    headers: HeaderMap,
+    Extension(state): Extension<FeedState>,
 ) -> Result<Response, Error> {
-    tracing::info!("get feed '{}'", handle);
-
-    let feed = {
-        let handle = handle.clone();
-        let http_client = http_client.clone();
-        feed_cache
-            .get_cached(handle.clone(), || {
-                Box::pin(async move {
-                    match proxy::proxy_feed(&handle, &http_client).await {
-                        Ok(feed) => Ok::<_, Error>(Some(feed)),
-                        Err(err) => {
-                            tracing::error!("failed to get data from channel '{handle}': {err}");
-                            Ok::<_, Error>(None)
-                        }
-                    }
-                })
-            })
-            .await?
-    }
-    .ok_or(Error::Proxy(handle.clone()))?;
+    state
+        .serve_feed(FeedSource::Handle(handle), filter, &headers)
+        .await
+}
+
+async fn get_channel_feed(
+    Path(channel_id): Path<String>,
+    Query(filter): Query<Filter>,
+    headers: HeaderMap,
+    Extension(state): Extension<FeedState>,
+) -> Result<Response, Error> {
+    state
+        .serve_feed(FeedSource::ChannelId(channel_id), filter, &headers)
+        .await
+}
+
+async fn get_custom_url_feed(
+    Path(name): Path<String>,
+    Query(filter): Query<Filter>,
+    headers: HeaderMap,
+    Extension(state): Extension<FeedState>,
+) -> Result<Response, Error> {
+    state
+        .serve_feed(FeedSource::CustomUrl(name), filter, &headers)
+        .await
+}
+
+async fn get_user_feed(
+    Path(name): Path<String>,
+    Query(filter): Query<Filter>,
+    headers: HeaderMap,
+    Extension(state): Extension<FeedState>,
+) -> Result<Response, Error> {
+    state
+        .serve_feed(FeedSource::User(name), filter, &headers)
+        .await
+}
+
+async fn get_playlist_feed(
+    Path(playlist_id): Path<String>,
+    Query(filter): Query<Filter>,
+    headers: HeaderMap,
+    Extension(state): Extension<FeedState>,
+) -> Result<Response, Error> {
+    state
+        .serve_feed(FeedSource::Playlist(playlist_id), filter, &headers)
+        .await
+}
+
+/// Number of items of the channel page that couldn't be parsed and are missing from the feed
+const SKIPPED_HEADER: &str = "X-Ytfeed-Skipped";
 
-    let filtered_feed = filter.apply(feed)?;
+/// Maximum number of feeds in a `/merge` request, since each feed can take several requests to
+/// YouTube
+const MAX_MERGED_FEEDS: usize = 50;
 
-    let feed_str = filtered_feed
-        .into_atom(&base_url, &handle, &filter.query_string()?)
-        .to_string();
+/// Number of feeds of a merged feed that are fetched at the same time
+const MERGE_CONCURRENCY: usize = 4;
 
+/// Feeds to merge, e.g. `/merge?h=@a&h=@b`
+#[derive(Debug, Deserialize, Serialize)]
+struct MergeQuery {
+    #[serde(rename = "h", default)]
+    sources: Vec<String>,
+}
+
+async fn get_merged_feed(
+    Query(merge): Query<MergeQuery>,
+    Query(filter): Query<Filter>,
+    headers: HeaderMap,
+    Extension(state): Extension<FeedState>,
+) -> Result<Response, Error> {
+    if merge.sources.is_empty() {
+        return Err(Error::NothingToMerge);
+    }
+    if merge.sources.len() > MAX_MERGED_FEEDS {
+        return Err(Error::TooManyFeeds(MAX_MERGED_FEEDS));
+    }
+    let sources = merge
+        .sources
+        .iter()
+        .map(|source| source.parse())
+        .collect::<Result<Vec<FeedSource>, _>>()?;
+    let query_string = [serde_html_form::to_string(&merge)?, filter.query_string()?]
+        .into_iter()
+        .filter(|query| !query.is_empty())
+        .collect::<Vec<_>>()
+        .join("&");
+    let title = format!("Merged feed of {}", merge.sources.join(", "));
+    state
+        .serve_merged(title, "merge", &query_string, &sources, filter, &headers)
+        .await
+}
+
+async fn get_group_feed(
+    Path(name): Path<String>,
+    Query(filter): Query<Filter>,
+    headers: HeaderMap,
+    Extension(state): Extension<FeedState>,
+) -> Result<Response, Error> {
+    let sources = state
+        .groups
+        .get(&name)
+        .ok_or_else(|| Error::GroupNotFound(name.clone()))?;
+    state
+        .serve_merged(
+            name.clone(),
+            &format!("group/{name}"),
+            &filter.query_string()?,
+            sources,
+            filter,
+            &headers,
+        )
+        .await
+}
+
+/// Converts an OPML subscription list of YouTube feeds to ytfeed feeds, with the filter options
+/// of the query string
+async fn convert_opml(
+    Query(filter): Query<Filter>,
+    Extension(state): Extension<FeedState>,
+    opml: String,
+) -> Result<Response, Error> {
+    let (opml, count) = opml::convert(&opml, &state.base_url, &filter.query_string()?)?;
+    tracing::info!("converted {count} feeds in OPML");
     Ok(Response::builder()
-        // officially the atom MIME type is application/atom+xml, but text/xml is more widely supported
-        .header("Content-Type", "text/xml")
-        .body(Body::from(feed_str))
+        .header(CONTENT_TYPE, "text/x-opml")
+        .header(CONTENT_DISPOSITION, "attachment; filename=\"ytfeed.opml\"")
+        .body(Body::from(opml))
         .unwrap())
 }
 
+/// Shared state used to serve feeds
+#[derive(Clone)]
+struct FeedState {
+    proxy: Proxy,
+    channel_ids: Cache<FeedSource, String>,
+    feed_cache: Cache<FeedKey, Feed>,
+    base_url: String,
+    media: Option<MediaStore>,
+    /// Named groups of feeds to merge
+    groups: Arc<HashMap<String, Vec<FeedSource>>>,
+    /// Filter options used when a request doesn't set them
+    default_filter: Arc<Filter>,
+    ad_keywords: Arc<[String]>,
+}
+
+impl FeedState {
+    async fn serve_feed(
+        &self,
+        source: FeedSource,
+        filter: Filter,
+        headers: &HeaderMap,
+    ) -> Result<Response, Error> {
+        tracing::info!("get feed '{}'", source);
+        let filter = filter.with_defaults(&self.default_filter);
+        let feed = self.cached_feed(&source, &filter).await?;
+        let feed = Cached {
+            value: filter.apply(feed.value, &self.ad_keywords)?,
+            ..feed
+        };
+        self.respond(
+            feed,
+            &filter,
+            &source.path(),
+            &filter.query_string()?,
+            headers,
+        )
+        .await
+    }
+
+    /// Serves the feeds of several sources as one feed, sources that fail are left out
+    async fn serve_merged(
+        &self,
+        title: String,
+        path: &str,
+        query_string: &str,
+        sources: &[FeedSource],
+        filter: Filter,
+        headers: &HeaderMap,
+    ) -> Result<Response, Error> {
+        tracing::info!("get merged feed '{}' of {} feeds", path, sources.len());
+        let filter = filter.with_defaults(&self.default_filter);
+        // collected first, the compiler can't prove that a stream over the lazy iterator is `Send`
+        let feeds: Vec<_> = sources
+            .iter()
+            .map(|source| self.cached_feed(source, &filter))
+            .collect();
+        let results: Vec<_> = stream::iter(feeds)
+            .buffered(MERGE_CONCURRENCY)
+            .collect()
+            .await;
+        let mut feeds = Vec::new();
+        let mut expires_in = None;
+        let mut stale = false;
+        for (source, result) in sources.iter().zip(results) {
+            match result {
+                Ok(feed) => {
+                    feeds.push(filter.apply(feed.value, &self.ad_keywords)?);
+                    expires_in = expires_in.into_iter().chain(feed.expires_in).min();
+                    stale |= feed.stale;
+                }
+                Err(_) => tracing::warn!("leaving '{source}' out of merged feed '{path}'"),
+            }
+        }
+        if feeds.is_empty() {
+            return Err(Error::Proxy(path.to_string()));
+        }
+        let channel = Channel {
+            title,
+            id: path.to_string(),
+            url: self.base_url.clone() + path,
+        };
+        let feed = Cached {
+            value: Feed::merge(channel, feeds),
+            expires_in,
+            stale,
+        };
+        self.respond(feed, &filter, path, query_string, headers)
+            .await
+    }
+
+    /// Refreshes the feeds that are requested often before they expire, so that feed readers
+    /// don't have to wait for them
+    fn spawn_refresher(&self, interval: Duration, min_hits: u32) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let mut timer = tokio::time::interval(interval);
+            loop {
+                timer.tick().await;
+                for key in state.feed_cache.expiring(2 * interval, min_hits) {
+                    let proxy = state.proxy.clone();
+                    state
+                        .feed_cache
+                        .refresh(key.clone(), || fetch_feed(proxy, key));
+                }
+            }
+        });
+    }
+
+    /// Gets the unfiltered feed of a source from the cache, an expired feed is served if it can't
+    /// be refreshed
+    async fn cached_feed(
+        &self,
+        source: &FeedSource,
+        filter: &Filter,
+    ) -> Result<Cached<Feed>, Error> {
+        // channels are cached by id, regardless of the name they were requested by
+        let resolved = self
+            .proxy
+            .resolve_source(source.clone(), &self.channel_ids)
+            .await
+            .map_err(|err| {
+                tracing::error!("failed to resolve '{source}': {err}");
+                upstream_error(source, err.into())
+            })?;
+        let key = FeedKey::new(resolved, filter.tabs());
+
+        self.feed_cache
+            .get_cached_or_stale(key.clone(), || fetch_feed(self.proxy.clone(), key))
+            .await
+            .map_err(|err| upstream_error(source, err))
+    }
+
+    /// Renders a filtered feed in the negotiated format
+    async fn respond(
+        &self,
+        feed: Cached<Feed>,
+        filter: &Filter,
+        path: &str,
+        query_string: &str,
+        headers: &HeaderMap,
+    ) -> Result<Response, Error> {
+        let Cached {
+            value: mut feed,
+            expires_in,
+            stale,
+        } = feed;
+        let accept = headers.get(ACCEPT).and_then(|accept| accept.to_str().ok());
+        let (format, content_type) = Format::negotiate(filter.format, accept);
+        if format == Format::Podcast {
+            let media = self.media.as_ref().ok_or(Error::NoMediaDir)?;
+            media.attach(&mut feed).await;
+        }
+        let last_modified = feed.updated();
+        let skipped = feed.skipped;
+        let feed_str = feed.render(format, &self.base_url, path, query_string)?;
+
+        let validators = Validators::new(&feed_str, Some(last_modified), expires_in);
+        // the same URL is rendered differently depending on the Accept header
+        let mut response = validators
+            .headers(Response::builder())
+            .header(VARY, "Accept");
+        if stale {
+            response = response.header(WARNING, "110 - \"Response is Stale\"");
+        }
+        if skipped > 0 {
+            response = response.header(SKIPPED_HEADER, skipped);
+        }
+        if validators.is_not_modified(headers) {
+            return Ok(response
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .unwrap());
+        }
+        Ok(response
+            .header(CONTENT_TYPE, content_type)
+            .body(Body::from(feed_str))
+            .unwrap())
+    }
+}
+
+/// Missing channels and playlists are answered with 404, other failures with 502
+fn upstream_error(source: &FeedSource, err: CacheError) -> Error {
+    if !err.is_not_found() {
+        return Error::Proxy(source.to_string());
+    }
+    match source {
+        FeedSource::Playlist(id) => Error::PlaylistNotFound(id.clone()),
+        _ => Error::ChannelNotFound(source.to_string()),
+    }
+}
+
+/// Fetches a feed for the cache
+fn fetch_feed(proxy: Proxy, key: FeedKey) -> BoxFut<'static, Result<Feed, Error>> {
+    Box::pin(async move {
+        proxy.proxy_feed(&key).await.inspect_err(|err| {
+            tracing::error!("failed to get data from '{}': {err}", key.source);
+        })
+    })
+}
+
 async fn get_health() -> StatusCode {
     StatusCode::OK
 }
@@ -0,0 +1,91 @@
+use crate::feed::Feed;
+use std::path::{Path, PathBuf};
+
+/// Extensions and MIME types of the audio files that are served, in order of preference
+const AUDIO_TYPES: &[(&str, &str)] = &[
+    ("m4a", "audio/mp4"),
+    ("mp3", "audio/mpeg"),
+    ("opus", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("aac", "audio/aac"),
+    ("webm", "audio/webm"),
+];
+
+/// Directory with audio files named by video id (e.g. `dQw4w9WgXcQ.m4a`), which is filled by an
+/// external downloader
+#[derive(Debug, Clone)]
+pub struct MediaStore {
+    dir: PathBuf,
+}
+
+/// An audio file of a video in the [`MediaStore`]
+#[derive(Debug, Clone)]
+pub struct MediaFile {
+    pub file_name: String,
+    /// Size in bytes
+    pub length: u64,
+    pub mime_type: &'static str,
+}
+
+impl MediaStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Finds the audio file of a video
+    pub async fn find(&self, video_id: &str) -> Option<MediaFile> {
+        for (extension, mime_type) in AUDIO_TYPES {
+            let file_name = format!("{video_id}.{extension}");
+            let Ok(metadata) = tokio::fs::metadata(self.dir.join(&file_name)).await else {
+                continue;
+            };
+            if metadata.is_file() {
+                return Some(MediaFile {
+                    file_name,
+                    length: metadata.len(),
+                    mime_type,
+                });
+            }
+        }
+        None
+    }
+
+    /// Attaches the audio files to the videos of a feed that have been downloaded
+    pub async fn attach(&self, feed: &mut Feed) {
+        for video in &mut feed.videos {
+            video.audio = self.find(&video.id).await;
+        }
+        let count = feed.videos.iter().filter(|v| v.audio.is_some()).count();
+        tracing::debug!(
+            "found audio files for {} of {} videos",
+            count,
+            feed.videos.len()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn find() {
+        let dir = std::env::temp_dir().join(format!("ytfeed-media-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("vidLockup01.mp3"), [0; 42]).unwrap();
+        std::fs::write(dir.join("vidLockup01.part"), [0; 7]).unwrap();
+        let media = MediaStore::new(dir.clone());
+
+        let file = media.find("vidLockup01").await.unwrap();
+        assert_eq!(file.file_name, "vidLockup01.mp3");
+        assert_eq!(file.length, 42);
+        assert_eq!(file.mime_type, "audio/mpeg");
+        assert!(media.find("vidLockup02").await.is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
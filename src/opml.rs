@@ -0,0 +1,174 @@
+//! Conversion of OPML subscription lists from YouTube feeds to ytfeed feeds
+
+use crate::{error::Error, proxy::FeedSource};
+use quick_xml::{
+    encoding::Decoder,
+    events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event},
+    Reader, Writer,
+};
+use reqwest::Url;
+
+/// A feed in an OPML subscription list
+#[derive(Debug, Clone)]
+pub struct Outline {
+    pub text: String,
+    pub xml_url: String,
+    pub html_url: String,
+}
+
+/// Writes an OPML subscription list with the feeds
+pub fn write(title: &str, outlines: &[Outline]) -> Result<String, Error> {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    let events = [
+        Event::Decl(BytesDecl::new("1.0", Some("UTF-8"), None)),
+        Event::Start(BytesStart::new("opml").with_attributes([("version", "2.0")])),
+        Event::Start(BytesStart::new("head")),
+        Event::Start(BytesStart::new("title")),
+        Event::Text(BytesText::new(title)),
+        Event::End(BytesEnd::new("title")),
+        Event::End(BytesEnd::new("head")),
+        Event::Start(BytesStart::new("body")),
+    ]
+    .into_iter()
+    .chain(outlines.iter().map(|outline| {
+        Event::Empty(BytesStart::new("outline").with_attributes([
+            ("type", "rss"),
+            ("text", outline.text.as_str()),
+            ("title", outline.text.as_str()),
+            ("xmlUrl", outline.xml_url.as_str()),
+            ("htmlUrl", outline.html_url.as_str()),
+        ]))
+    }))
+    .chain([
+        Event::End(BytesEnd::new("body")),
+        Event::End(BytesEnd::new("opml")),
+    ]);
+    for event in events {
+        writer.write_event(event).map_err(quick_xml::Error::from)?;
+    }
+    let mut opml = String::from_utf8(writer.into_inner()).expect("input is valid UTF-8");
+    opml.push('\n');
+    Ok(opml)
+}
+
+/// Rewrites the outlines with a YouTube RSS feed to the matching ytfeed URL with the query
+/// string, other elements are copied as-is. Returns the OPML and the number of rewritten feeds.
+pub fn convert(opml: &str, base_url: &str, query_string: &str) -> Result<(String, usize), Error> {
+    let mut reader = Reader::from_str(opml);
+    let mut writer = Writer::new(Vec::new());
+    let mut converted = 0;
+    let decoder = reader.decoder();
+    loop {
+        let event = match reader.read_event()? {
+            Event::Eof => break,
+            Event::Start(element) if element.name().as_ref() == b"outline" => Event::Start(
+                convert_outline(element, decoder, base_url, query_string, &mut converted)?,
+            ),
+            Event::Empty(element) if element.name().as_ref() == b"outline" => Event::Empty(
+                convert_outline(element, decoder, base_url, query_string, &mut converted)?,
+            ),
+            event => event,
+        };
+        writer.write_event(event).map_err(quick_xml::Error::from)?;
+    }
+    let opml = String::from_utf8(writer.into_inner()).expect("input is valid UTF-8");
+    Ok((opml, converted))
+}
+
+fn convert_outline<'a>(
+    element: BytesStart<'a>,
+    decoder: Decoder,
+    base_url: &str,
+    query_string: &str,
+    converted: &mut usize,
+) -> Result<BytesStart<'a>, Error> {
+    let mut outline = BytesStart::new("outline");
+    for attr in element.attributes() {
+        let attr = attr.map_err(quick_xml::Error::from)?;
+        let source = (attr.key.as_ref() == b"xmlUrl")
+            .then(|| attr.decode_and_unescape_value(decoder))
+            .transpose()?
+            .and_then(|url| youtube_feed_source(&url));
+        match source {
+            Some(source) => {
+                let mut url = base_url.to_string() + &source.path();
+                if !query_string.is_empty() {
+                    url.push('?');
+                    url.push_str(query_string);
+                }
+                outline.push_attribute(("xmlUrl", url.as_str()));
+                *converted += 1;
+            }
+            None => outline.push_attribute(attr),
+        }
+    }
+    Ok(outline)
+}
+
+/// Source of a YouTube RSS feed URL (`https://www.youtube.com/feeds/videos.xml?channel_id=...`)
+fn youtube_feed_source(url: &str) -> Option<FeedSource> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if !(host == "youtube.com" || host.ends_with(".youtube.com"))
+        || url.path() != "/feeds/videos.xml"
+    {
+        return None;
+    }
+    url.query_pairs()
+        .find_map(|(key, value)| match key.as_ref() {
+            "channel_id" => Some(FeedSource::ChannelId(value.into_owned())),
+            "playlist_id" => Some(FeedSource::Playlist(value.into_owned())),
+            "user" => Some(FeedSource::User(value.into_owned())),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stub;
+
+    #[test]
+    fn convert_subscriptions() {
+        let opml = stub::fixture("subscriptions.opml");
+        let (converted, count) = convert(&opml, "http://localhost:8000/", "d=600&b=true").unwrap();
+
+        assert_eq!(count, 3);
+        assert!(converted.contains(
+            r#"xmlUrl="http://localhost:8000/channel/UClockupLayout00000000000?d=600&amp;b=true""#
+        ));
+        assert!(converted
+            .contains(r#"htmlUrl="https://www.youtube.com/channel/UClockupLayout00000000000""#));
+        assert!(converted.contains(r#"text="Tom &amp; Jerry's Playlist""#));
+        assert!(converted.contains(
+            r#"xmlUrl="http://localhost:8000/playlist/PLplaylist0000000000000000000000?d=600&amp;b=true""#
+        ));
+        assert!(converted
+            .contains(r#"xmlUrl="http://localhost:8000/user/LegacyUser?d=600&amp;b=true""#));
+        assert!(
+            converted.contains(r#"xmlUrl="https://example.com/feed.xml?channel_id=UCnotYouTube""#)
+        );
+        // the folder structure is kept
+        assert!(converted.contains(r#"<outline text="YouTube" title="YouTube">"#));
+
+        let (_, count) = convert(&converted, "http://localhost:8000/", "").unwrap();
+        assert_eq!(count, 0);
+        assert!(convert("<opml><body></outline></opml>", "/", "").is_err());
+    }
+    #[test]
+    fn write_outlines() {
+        let outline = Outline {
+            text: "Tom & Jerry".to_string(),
+            xml_url: "http://localhost:8000/channel/UC1?d=600&b=true".to_string(),
+            html_url: "https://www.youtube.com/channel/UC1".to_string(),
+        };
+        let opml = write("Subscriptions", &[outline]).unwrap();
+
+        assert!(opml.contains("<title>Subscriptions</title>"));
+        assert!(opml.contains(r#"text="Tom &amp; Jerry""#));
+        assert!(opml.contains(r#"xmlUrl="http://localhost:8000/channel/UC1?d=600&amp;b=true""#));
+        // feeds that are already converted are kept
+        let (_, count) = convert(&opml, "http://localhost:8000/", "").unwrap();
+        assert_eq!(count, 0);
+    }
+}
@@ -1,71 +1,290 @@
 use crate::{
+    cache::Cache,
     error::Error,
-    extractor::{self},
-    feed::{Feed, Video},
+    extractor::{Extraction, Extractor, Tab},
+    feed::{Channel, Feed, Video},
 };
-use atom_syndication::Feed as AtomFeed;
+use atom_syndication::{Entry, Feed as AtomFeed};
 use bytes::Buf;
+use chrono::Utc;
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr, sync::Arc};
 
-/// Proxies a YouTube channel feed, filters out shorts from the RSS feed by extracting video
-/// information from the channel page
-pub async fn proxy_feed(handle: &str, client: &Client) -> Result<Feed, Error> {
-    // 1. extract channel data and video information from YouTube
-    let mut extraction = extractor::extract_data(handle, client).await?;
+/// A YouTube feed that can be proxied
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum FeedSource {
+    /// Uploads of a channel, by handle (without the '@')
+    Handle(String),
+    /// Uploads of a channel, by channel id (starting with 'UC')
+    ChannelId(String),
+    /// Uploads of a channel, by legacy custom URL name (`/c/Name`)
+    CustomUrl(String),
+    /// Uploads of a channel, by legacy username (`/user/Name`)
+    User(String),
+    /// Videos in a playlist, by playlist id
+    Playlist(String),
+}
+
+impl FeedSource {
+    /// Path of the feed relative to the base URL
+    pub fn path(&self) -> String {
+        match self {
+            FeedSource::Handle(handle) => format!("@{handle}"),
+            FeedSource::ChannelId(id) => format!("channel/{id}"),
+            FeedSource::CustomUrl(name) => format!("c/{name}"),
+            FeedSource::User(name) => format!("user/{name}"),
+            FeedSource::Playlist(id) => format!("playlist/{id}"),
+        }
+    }
+}
+
+/// Parses a feed path as used in the URL (e.g. `@handle` or `playlist/PL...`), the inverse of
+/// [`FeedSource::path`]
+impl FromStr for FeedSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Error::InvalidSource(s.to_string());
+        let source = s.trim().trim_matches('/');
+        let (kind, name) = match source.strip_prefix('@') {
+            Some(handle) => ("@", handle),
+            None => source.split_once('/').ok_or_else(invalid)?,
+        };
+        if name.is_empty() || name.contains('/') {
+            return Err(invalid());
+        }
+        let name = name.to_string();
+        match kind {
+            "@" => Ok(FeedSource::Handle(name)),
+            "channel" => Ok(FeedSource::ChannelId(name)),
+            "c" => Ok(FeedSource::CustomUrl(name)),
+            "user" => Ok(FeedSource::User(name)),
+            "playlist" => Ok(FeedSource::Playlist(name)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for FeedSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.path())
+    }
+}
+
+/// Identifies a proxied feed in the cache
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct FeedKey {
+    pub source: FeedSource,
+    /// Channel tabs to scrape, always empty for playlists
+    pub tabs: Vec<Tab>,
+}
+
+impl FeedKey {
+    pub fn new(source: FeedSource, tabs: Vec<Tab>) -> Self {
+        let tabs = match source {
+            FeedSource::Playlist(_) => Vec::new(),
+            _ => tabs,
+        };
+        Self { source, tabs }
+    }
+}
+
+/// Proxies YouTube feeds by combining extracted video information with the RSS feeds
+#[derive(Clone)]
+pub struct Proxy {
+    client: Client,
+    extractor: Arc<dyn Extractor>,
+    /// Maximum number of videos to extract from each channel tab
+    max_videos: usize,
+    /// Base URL of the server with the RSS feeds, [`YOUTUBE_URL`](crate::extractor::YOUTUBE_URL) unless testing
+    youtube_url: String,
+}
+
+impl Proxy {
+    pub fn new(
+        client: Client,
+        extractor: Arc<dyn Extractor>,
+        max_videos: usize,
+        youtube_url: &str,
+    ) -> Self {
+        Self {
+            client,
+            extractor,
+            max_videos,
+            youtube_url: youtube_url.to_string(),
+        }
+    }
+
+    /// Resolves channel names to a [`FeedSource::ChannelId`], so that the same channel reached by
+    /// different names shares a single feed. Resolved ids are kept in the cache.
+    pub async fn resolve_source(
+        &self,
+        source: FeedSource,
+        channel_ids: &Cache<FeedSource, String>,
+    ) -> Result<FeedSource, Error> {
+        match source {
+            FeedSource::Handle(_) | FeedSource::CustomUrl(_) | FeedSource::User(_) => {
+                let extractor = self.extractor.clone();
+                let channel_id = channel_ids
+                    .get_cached(source.clone(), || {
+                        Box::pin(async move {
+                            tracing::debug!("resolving channel id of '{source}'");
+                            extractor.extract_channel_id(&source.path()).await
+                        })
+                    })
+                    .await?;
+                Ok(FeedSource::ChannelId(channel_id))
+            }
+            FeedSource::ChannelId(_) | FeedSource::Playlist(_) => Ok(source),
+        }
+    }
+
+    /// Proxies a YouTube feed, filters out shorts from the RSS feed by extracting video
+    /// information from the channel or playlist page
+    pub async fn proxy_feed(&self, key: &FeedKey) -> Result<Feed, Error> {
+        match &key.source {
+            FeedSource::Handle(_)
+            | FeedSource::ChannelId(_)
+            | FeedSource::CustomUrl(_)
+            | FeedSource::User(_) => {
+                // 1. extract channel data and video information from YouTube
+                let extraction = self
+                    .extractor
+                    .extract_channel(&key.source.path(), &key.tabs, self.max_videos)
+                    .await?;
+                if extraction.complete {
+                    return Ok(extracted_feed(extraction));
+                }
+                // 2. Use channel id to fetch feed from YouTube RSS server
+                let feed = self
+                    .fetch_feed("channel_id", &extraction.channel.id)
+                    .await?;
+                Ok(merge_feed(extraction, feed))
+            }
+            FeedSource::Playlist(playlist_id) => {
+                let extraction = self.extractor.extract_playlist(playlist_id).await?;
+                if extraction.complete {
+                    return Ok(extracted_feed(extraction));
+                }
+                let feed = self.fetch_feed("playlist_id", playlist_id).await?;
+                Ok(merge_feed(extraction, feed))
+            }
+        }
+    }
+
+    /// Get a feed from the YouTube RSS server, `key` is either `channel_id` or `playlist_id`
+    async fn fetch_feed(&self, key: &str, id: &str) -> Result<AtomFeed, Error> {
+        let feed_url = format!("{}/feeds/videos.xml?{}={}", self.youtube_url, key, id);
+        tracing::debug!("fetching feed from {}", feed_url);
+        match try_fetch_feed(&feed_url, &self.client).await {
+            Ok(feed) => Ok(feed),
+            Err(err) if is_transient(&err) => {
+                tracing::warn!("transient error fetching feed, retrying once: {err}");
+                try_fetch_feed(&feed_url, &self.client).await
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Match & combine both data sources into a single feed
+fn merge_feed(mut extraction: Extraction, feed: AtomFeed) -> Feed {
     let extracted_videos_count = extraction.videos.len();
-    // 2. Use channel id to fetch feed from YouTube RSS server
-    let feed = fetch_feed(&extraction.channel.id, client).await?;
     let feed_entries_count = feed.entries.len();
+    let mut skipped = extraction.skipped;
 
-    // 3. Match & combine both data sources into a single feed
-    // This process automatically filters out shorts since the channel data extraction only contains videos
+    // Entries are only kept if they were found on one of the scraped tabs, so shorts are dropped
+    // unless the shorts tab was scraped. Shorts are then kept with their kind, so they can be
+    // filtered or tagged.
+    let mut matched_count = 0;
     let mut videos: Vec<Video> = feed
         .entries
         .into_iter()
         .filter_map(|e| {
-            if let Some(video_idx) = extraction
+            let video_idx = extraction
                 .videos
                 .iter()
-                .position(|v| Some(v.id.clone()) == e.extensions["yt"]["videoId"][0].value)
-            {
-                let video = extraction.videos.swap_remove(video_idx);
-                Some(Video::from_entry_and_info(e, video))
-            } else {
-                None
+                .position(|v| Some(v.id.as_str()) == entry_video_id(&e))?;
+            matched_count += 1;
+            let video = extraction.videos.swap_remove(video_idx);
+            match Video::from_entry_and_info(e, video) {
+                Ok(video) => Some(video),
+                Err(err) => {
+                    tracing::warn!("skipping feed entry: {err}");
+                    skipped += 1;
+                    None
+                }
             }
         })
         .collect();
+    let merged_count = videos.len();
+
+    // Older videos that didn't fit in the RSS feed are backfilled from the scraped data
+    videos.extend(
+        extraction
+            .videos
+            .into_iter()
+            .filter_map(|v| Video::from_info(v, true)),
+    );
 
     tracing::debug!(
-        "proxied {} videos ({} extracted, {} feed)",
+        "proxied {} videos ({} extracted, {} feed), dropped {} entries not on the scraped tabs, backfilled {}",
         videos.len(),
         extracted_videos_count,
-        feed_entries_count
+        feed_entries_count,
+        feed_entries_count - matched_count,
+        videos.len() - merged_count
     );
+    report_skipped(skipped, &extraction.channel);
 
     // sort by published date
-    videos.sort_by(|a, b| b.published.cmp(&a.published));
+    videos.sort_by_key(|v| std::cmp::Reverse(v.published));
 
-    Ok(Feed {
+    Feed {
         channel: extraction.channel,
         videos,
-    })
+        skipped,
+        fetched: Utc::now().fixed_offset(),
+    }
 }
 
-/// Get a feed from the YouTube RSS server
-async fn fetch_feed(channel_id: &str, client: &Client) -> Result<AtomFeed, Error> {
-    let feed_url = format!(
-        "https://www.youtube.com/feeds/videos.xml?channel_id={}",
-        channel_id
-    );
-    tracing::debug!("fetching feed from {}", feed_url);
-    match try_fetch_feed(&feed_url, client).await {
-        Ok(feed) => Ok(feed),
-        Err(err) if is_transient(&err) => {
-            tracing::warn!("transient error fetching feed, retrying once: {err}");
-            try_fetch_feed(&feed_url, client).await
-        }
-        Err(err) => Err(err),
+/// Builds a feed from an extraction that has all video data, without the RSS feed
+fn extracted_feed(extraction: Extraction) -> Feed {
+    let mut videos: Vec<Video> = extraction
+        .videos
+        .into_iter()
+        .filter_map(|v| Video::from_info(v, false))
+        .collect();
+    tracing::debug!("built feed with {} extracted videos", videos.len());
+    report_skipped(extraction.skipped, &extraction.channel);
+    videos.sort_by_key(|v| std::cmp::Reverse(v.published));
+    Feed {
+        channel: extraction.channel,
+        videos,
+        skipped: extraction.skipped,
+        fetched: Utc::now().fixed_offset(),
+    }
+}
+
+fn entry_video_id(entry: &Entry) -> Option<&str> {
+    entry
+        .extensions
+        .get("yt")?
+        .get("videoId")?
+        .first()?
+        .value
+        .as_deref()
+}
+
+/// Warns about items that were skipped because they couldn't be parsed
+fn report_skipped(skipped: usize, channel: &Channel) {
+    if skipped > 0 {
+        tracing::warn!(
+            "skipped {} items of '{}' that couldn't be parsed",
+            skipped,
+            channel.title
+        );
     }
 }
 
@@ -86,3 +305,171 @@ fn is_transient(err: &Error) -> bool {
         _ => false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        extractor::{self, Backend, Instances, VideoKind},
+        stub,
+    };
+    use std::time::Duration;
+
+    const ROUTES: &[(&str, &str)] = &[
+        ("/@lockup", "channel_lockup.html"),
+        ("/@lockup/videos", "channel_lockup.html"),
+        (
+            "/youtubei/v1/browse?prettyPrint=false",
+            "channel_lockup_continuation.json",
+        ),
+        (
+            "/feeds/videos.xml?channel_id=UClockupLayout00000000000",
+            "feed_lockup.xml",
+        ),
+        ("/@renderer", "channel_renderer.html"),
+        ("/@renderer/videos", "channel_renderer.html"),
+        (
+            "/feeds/videos.xml?channel_id=UCrendererLayout000000000",
+            "feed_renderer.xml",
+        ),
+        ("/playlist?list=PLfixture", "playlist.html"),
+        (
+            "/feeds/videos.xml?playlist_id=PLfixture",
+            "feed_playlist.xml",
+        ),
+    ];
+
+    async fn proxy() -> Proxy {
+        let url = stub::serve(ROUTES).await;
+        let client = Client::new();
+        let extractor =
+            extractor::with_fallback(Backend::Html, &url, &Instances::default(), client.clone())
+                .unwrap();
+        Proxy::new(client, extractor, 30, &url)
+    }
+
+    #[tokio::test]
+    async fn merge() {
+        let proxy = proxy().await;
+        let key = FeedKey::new(FeedSource::Handle("lockup".to_string()), vec![Tab::Videos]);
+        let feed = proxy.proxy_feed(&key).await.unwrap();
+
+        assert_eq!(feed.channel.id, "UClockupLayout00000000000");
+        // the short is not on the videos tab, the premiere has no publish date to backfill
+        let ids: Vec<&str> = feed.videos.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, ["vidLockup02", "vidLockup01", "vidLockup04"]);
+        let estimated: Vec<bool> = feed.videos.iter().map(|v| v.estimated).collect();
+        assert_eq!(estimated, [false, false, true]);
+        assert_eq!(feed.videos[1].likes, Some(5432));
+        assert_eq!(feed.videos[2].title, "Old video");
+    }
+
+    #[tokio::test]
+    async fn merge_renderer() {
+        let proxy = proxy().await;
+        let key = FeedKey::new(
+            FeedSource::Handle("renderer".to_string()),
+            vec![Tab::Videos],
+        );
+        let feed = proxy.proxy_feed(&key).await.unwrap();
+
+        assert_eq!(feed.channel.id, "UCrendererLayout000000000");
+        // the short is not on the videos tab
+        let ids: Vec<&str> = feed.videos.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, ["vidRender02", "vidRender03", "vidRender01"]);
+        let kinds: Vec<VideoKind> = feed.videos.iter().map(|v| v.kind).collect();
+        assert_eq!(
+            kinds,
+            [VideoKind::Upcoming, VideoKind::Live, VideoKind::Video]
+        );
+        assert!(feed.videos.iter().all(|v| !v.estimated));
+        let regular = &feed.videos[2];
+        assert_eq!(regular.duration, Duration::from_secs(245));
+        assert_eq!(regular.views, 321);
+        assert_eq!(regular.likes, Some(7));
+        assert_eq!(feed.videos[0].likes, None);
+    }
+
+    #[tokio::test]
+    async fn merge_playlist() {
+        let proxy = proxy().await;
+        let key = FeedKey::new(
+            FeedSource::Playlist("PLfixture".to_string()),
+            vec![Tab::Videos],
+        );
+        assert!(key.tabs.is_empty());
+        let feed = proxy.proxy_feed(&key).await.unwrap();
+
+        assert_eq!(feed.channel.id, "PLfixture");
+        assert_eq!(feed.channel.title, "Fixture Playlist");
+        // the video that is no longer in the playlist is dropped, the old video is backfilled
+        // and the premiere has no publish date to backfill
+        let ids: Vec<&str> = feed.videos.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            ["vidPlay0005", "vidPlay0004", "vidPlay0008", "vidPlay0001"]
+        );
+        let kinds: Vec<VideoKind> = feed.videos.iter().map(|v| v.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                VideoKind::Live,
+                VideoKind::Short,
+                VideoKind::Video,
+                VideoKind::Video
+            ]
+        );
+        let estimated: Vec<bool> = feed.videos.iter().map(|v| v.estimated).collect();
+        assert_eq!(estimated, [false, false, false, true]);
+        assert_eq!(feed.videos[2].likes, Some(2));
+        assert_eq!(feed.videos[2].duration, Duration::from_secs(245));
+        assert_eq!(feed.videos[3].views, 1_200_000);
+        assert_eq!(feed.skipped, 1);
+    }
+
+    #[tokio::test]
+    async fn resolve() {
+        let proxy = proxy().await;
+        let channel_ids = Cache::new(None);
+        let source = FeedSource::Handle("lockup".to_string());
+        assert_eq!(
+            proxy.resolve_source(source, &channel_ids).await.unwrap(),
+            FeedSource::ChannelId("UClockupLayout00000000000".to_string())
+        );
+        let playlist = FeedSource::Playlist("PL123".to_string());
+        assert_eq!(
+            proxy
+                .resolve_source(playlist.clone(), &channel_ids)
+                .await
+                .unwrap(),
+            playlist
+        );
+    }
+
+    #[test]
+    fn parse_source() {
+        let parse = |s: &str| s.parse::<FeedSource>().ok();
+        assert_eq!(parse("@a"), Some(FeedSource::Handle("a".to_string())));
+        assert_eq!(
+            parse("/channel/UC123/"),
+            Some(FeedSource::ChannelId("UC123".to_string()))
+        );
+        assert_eq!(
+            parse("c/Name"),
+            Some(FeedSource::CustomUrl("Name".to_string()))
+        );
+        assert_eq!(
+            parse("user/Name"),
+            Some(FeedSource::User("Name".to_string()))
+        );
+        assert_eq!(
+            parse("playlist/PL1"),
+            Some(FeedSource::Playlist("PL1".to_string()))
+        );
+        for invalid in ["a", "@", "channel/", "watch/abc", "channel/UC1/videos"] {
+            assert_eq!(parse(invalid), None, "{invalid}");
+        }
+        let source = FeedSource::Playlist("PL1".to_string());
+        assert_eq!(parse(&source.path()), Some(source));
+    }
+}
@@ -0,0 +1,34 @@
+//! Local HTTP server that serves saved YouTube responses, so tests don't need network access
+
+use axum::{
+    http::{StatusCode, Uri},
+    Router,
+};
+use tokio::net::TcpListener;
+
+/// Directory with the saved pages and feeds
+const FIXTURES_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+
+/// Reads a fixture file
+pub fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("{FIXTURES_DIR}/{name}"))
+        .unwrap_or_else(|err| panic!("failed to read fixture '{name}': {err}"))
+}
+
+/// Starts a server on a random local port and returns its base URL
+///
+/// Each route maps a path and query (e.g. `/feeds/videos.xml?channel_id=UC...`) to the fixture
+/// that is served for it, regardless of the request method. Other requests get a 404.
+pub async fn serve(routes: &'static [(&'static str, &'static str)]) -> String {
+    let router = Router::new().fallback(move |uri: Uri| async move {
+        let path_and_query = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+        match routes.iter().find(|(route, _)| *route == path_and_query) {
+            Some((_, name)) => (StatusCode::OK, fixture(name)),
+            None => (StatusCode::NOT_FOUND, String::new()),
+        }
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    format!("http://{address}")
+}
@@ -0,0 +1,168 @@
+//! Import of the YouTube subscriptions from a [Google Takeout](https://takeout.google.com/) export
+
+use crate::{
+    cache::Cache,
+    error::Error,
+    opml,
+    proxy::{FeedSource, Proxy},
+};
+use clap::{Args, ValueEnum};
+use reqwest::Url;
+use std::{collections::BTreeMap, io::Read, path::PathBuf};
+
+#[derive(Args, Debug, Clone)]
+pub struct ImportArgs {
+    /// The `subscriptions.csv` file of the YouTube export, `-` reads from stdin
+    csv: PathBuf,
+    /// Output format
+    #[arg(short = 'f', long = "format", value_enum, default_value_t = ImportFormat::Opml)]
+    format: ImportFormat,
+    /// Filter options added to each feed, e.g. `d=600&b=true`
+    #[arg(short = 'q', long = "query", default_value = "")]
+    query: String,
+    /// Name of the group, for the group format
+    #[arg(long = "name", default_value = "subscriptions")]
+    name: String,
+    /// Output file, stdout if not set
+    #[arg(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ImportFormat {
+    /// OPML file with a feed for each channel, to import in a feed reader
+    Opml,
+    /// Group of all channels as merged feed, as `[groups]` table of the config file
+    Group,
+}
+
+/// A row of `subscriptions.csv`
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Subscription {
+    channel_id: String,
+    url: String,
+    title: String,
+}
+
+impl Subscription {
+    /// The channel id if it is exported, otherwise the channel URL
+    fn source(&self) -> Result<FeedSource, Error> {
+        if !self.channel_id.is_empty() {
+            return Ok(FeedSource::ChannelId(self.channel_id.clone()));
+        }
+        let url = Url::parse(&self.url).map_err(|_| Error::InvalidSource(self.url.clone()))?;
+        url.path().parse()
+    }
+}
+
+/// Parses the CSV export, the columns are the channel id, URL and title. The header is skipped
+/// by position, since its names are translated.
+fn read_subscriptions(csv: impl Read) -> Result<Vec<Subscription>, Error> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(csv);
+    let mut subscriptions = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let field = |idx| record.get(idx).unwrap_or_default().trim().to_string();
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+        subscriptions.push(Subscription {
+            channel_id: field(0),
+            url: field(1),
+            title: field(2),
+        });
+    }
+    Ok(subscriptions)
+}
+
+/// Resolves the subscriptions to feeds and writes them in the chosen format, channels that can't
+/// be resolved are left out
+pub async fn import(args: ImportArgs, proxy: &Proxy, base_url: &str) -> Result<(), Error> {
+    let subscriptions = if args.csv.as_os_str() == "-" {
+        read_subscriptions(std::io::stdin().lock())?
+    } else {
+        read_subscriptions(std::fs::File::open(&args.csv)?)?
+    };
+    tracing::info!("importing {} subscriptions", subscriptions.len());
+
+    let channel_ids = Cache::new(None);
+    let mut feeds = Vec::new();
+    for subscription in subscriptions {
+        // the same resolution as feed requests, e.g. `/channel/{id}`
+        let source = match subscription.source() {
+            Ok(source) => proxy.resolve_source(source, &channel_ids).await,
+            Err(err) => Err(err),
+        };
+        match source {
+            Ok(source) => feeds.push((subscription, source)),
+            Err(err) => tracing::warn!("skipping '{}': {err}", subscription.title),
+        }
+    }
+
+    let output = match args.format {
+        ImportFormat::Opml => {
+            let outlines: Vec<opml::Outline> = feeds
+                .into_iter()
+                .map(|(subscription, source)| opml::Outline {
+                    text: subscription.title,
+                    xml_url: feed_url(base_url, &source, &args.query),
+                    html_url: subscription.url,
+                })
+                .collect();
+            opml::write("YouTube subscriptions", &outlines)?
+        }
+        ImportFormat::Group => {
+            let sources: Vec<String> = feeds.iter().map(|(_, source)| source.path()).collect();
+            let groups = BTreeMap::from([("groups", BTreeMap::from([(args.name, sources)]))]);
+            toml::to_string(&groups).expect("groups are valid TOML")
+        }
+    };
+    match args.output {
+        Some(path) => std::fs::write(path, output)?,
+        None => print!("{output}"),
+    }
+    Ok(())
+}
+
+fn feed_url(base_url: &str, source: &FeedSource, query: &str) -> String {
+    let mut url = base_url.to_string() + &source.path();
+    let query = query.trim_start_matches('?');
+    if !query.is_empty() {
+        url.push('?');
+        url.push_str(query);
+    }
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subscriptions() {
+        let csv = "Channel Id,Channel Url,Channel Title\n\
+            UClockupLayout00000000000,http://www.youtube.com/channel/UClockupLayout00000000000,Lockup Layout\n\
+            ,https://www.youtube.com/@handle,\"Quoted, with comma\"\n\
+            \n";
+        let subscriptions = read_subscriptions(csv.as_bytes()).unwrap();
+
+        assert_eq!(subscriptions.len(), 2);
+        assert_eq!(subscriptions[1].title, "Quoted, with comma");
+        assert_eq!(
+            subscriptions[0].source().unwrap(),
+            FeedSource::ChannelId("UClockupLayout00000000000".to_string())
+        );
+        assert_eq!(
+            subscriptions[1].source().unwrap(),
+            FeedSource::Handle("handle".to_string())
+        );
+        assert_eq!(
+            feed_url(
+                "http://localhost:8000/",
+                &subscriptions[0].source().unwrap(),
+                "?d=600"
+            ),
+            "http://localhost:8000/channel/UClockupLayout00000000000?d=600"
+        );
+    }
+}